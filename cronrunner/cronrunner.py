@@ -16,125 +16,605 @@
 # You should have received a copy of the GNU General Public License
 # along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+import argparse
+import errno
+import getpass
+import json
+import os
 import re
+import shlex
+import shutil
+import signal
+import stat
 import subprocess
 import sys
-from dataclasses import dataclass
-from pathlib import Path
+import tempfile
+from dataclasses import asdict, dataclass, field
+from datetime import datetime, timedelta
+from pathlib import Path, PureWindowsPath
+from typing import Callable, ClassVar, Optional, TextIO
+
+try:
+    import fcntl
+    import resource
+except ImportError:  # Not Unix.
+    fcntl = None
+    resource = None
 
 
 class CrontabReadError(Exception):
-    def __init__(self, *args, exit_code: int = 1, detail: str = "") -> None:
+    def __init__(
+        self,
+        *args,
+        exit_code: int = 1,
+        signal: Optional[int] = None,
+        detail: str = "",
+    ) -> None:
         self.exit_code: int = exit_code
+        self.signal: Optional[int] = signal
         self.detail: str = detail
         super().__init__(*args)
 
 
 class CrontabReader:
+    # Informational lines some `crontab` implementations append to their
+    # output, and which are not part of the crontab.
+    TRAILING_BANNERS: tuple = (r"no crontab for \S+",)
+
+    def __init__(self, user: Optional[str] = None) -> None:
+        """Create a reader.
+
+        `user` is whose crontab `read()` reads, instead of the current
+        user's. Reading another user's crontab requires privileges.
+        """
+        self.user: Optional[str] = user
+
+    def read(self) -> str:
+        if self.user is not None:
+            return self.read_for_user(self.user)
+        return self._read(["crontab", "-l"], "current user")
+
+    @classmethod
+    def read_for_user(cls, user: str) -> str:
+        return cls._read(["crontab", "-u", user, "-l"], f"user {user}")
+
     @staticmethod
-    def read() -> str:
+    def _read(command: list, owner: str) -> str:
         try:
             process: subprocess.CompletedProcess = subprocess.run(
-                ["crontab", "-l"],
+                command,
                 capture_output=True,
                 text=True,
                 check=True,
             )
         except subprocess.CalledProcessError as e:
+            # A negative return code means the process was killed by a
+            # signal (e.g., -11 for SIGSEGV). There is no meaningful exit
+            # code in that case.
+            killed: bool = e.returncode < 0
             raise CrontabReadError(
-                "Cannot read crontab of current user.",
-                exit_code=e.returncode,
+                f"Cannot read crontab of {owner}.",
+                exit_code=1 if killed else e.returncode,
+                signal=-e.returncode if killed else None,
                 detail=e.stderr,
             )
         except FileNotFoundError:
             raise CrontabReadError("Unable to locate crontab executable on the system.")
         return process.stdout
 
+    def read_normalized(self) -> str:
+        return self.normalize(self.read())
+
+    @classmethod
+    def normalize(cls, raw: str) -> str:
+        """Normalize line endings and strip known trailing banners.
+
+        Some `crontab` implementations output CRLF line endings, or
+        append an informational line after the crontab itself. Neither
+        belongs to the crontab, and banners would end up as unknown
+        lines in the parser.
+        """
+        lines: list = raw.replace("\r\n", "\n").replace("\r", "\n").split("\n")
+        while lines and not lines[-1].strip():
+            lines.pop()
+        if lines and any(
+            re.fullmatch(banner, lines[-1].strip()) for banner in cls.TRAILING_BANNERS
+        ):
+            lines.pop()
+        return "".join(f"{line}\n" for line in lines)
+
+
+def djb2(data: bytes) -> int:
+    """64-bit DJB2 hash."""
+    hash_: int = 5381
+    for byte in data:
+        hash_ = (hash_ * 33 + byte) & 0xFFFFFFFFFFFFFFFF
+    return hash_
+
+
+def fnv1a(data: bytes) -> int:
+    """64-bit FNV-1a hash."""
+    hash_: int = 0xCBF29CE484222325
+    for byte in data:
+        hash_ = ((hash_ ^ byte) * 0x100000001B3) & 0xFFFFFFFFFFFFFFFF
+    return hash_
+
+
+HASH_FUNCTIONS: dict = {"djb2": djb2, "fnv": fnv1a}
+
+
+def format_fingerprint(fingerprint: int) -> str:
+    """Return a fingerprint in its canonical form.
+
+    This is lowercase hexadecimal, without padding or prefix (e.g.,
+    `2b606`). Use it wherever fingerprints are shown or written.
+    """
+    return f"{fingerprint:x}"
+
+
+# Names cron accepts in place of numbers, per schedule field.
+SCHEDULE_NAMES: dict = {
+    "month": {
+        name: number
+        for number, name in enumerate(
+            "jan feb mar apr may jun jul aug sep oct nov dec".split(), start=1
+        )
+    },
+    "day_of_week": {
+        name: number
+        for number, name in enumerate("sun mon tue wed thu fri sat".split())
+    },
+}
+
+
+def schedule_names_to_numbers(value: str, field_name: str) -> str:
+    """Replace month or day names in a schedule field with numbers.
+
+    Names are case-insensitive, and may appear anywhere a number may
+    (e.g., `MON-FRI` becomes `1-5`, and `JAN,DEC` becomes `1,12`).
+
+    Raises `ValueError` if a name is not valid for the field.
+    """
+    names: dict = SCHEDULE_NAMES.get(field_name, {})
+
+    def to_number(match: re.Match) -> str:
+        name: str = match.group()
+        if name.lower() not in names:
+            raise ValueError(f"Invalid name for {field_name}: {name}.")
+        return str(names[name.lower()])
+
+    return re.sub(r"[a-zA-Z]+", to_number, value)
+
+
+SCHEDULE_SHORTCUTS: dict = {
+    "@yearly": "0 0 1 1 *",
+    "@annually": "0 0 1 1 *",
+    "@monthly": "0 0 1 * *",
+    "@weekly": "0 0 * * 0",
+    "@daily": "0 0 * * *",
+    "@midnight": "0 0 * * *",
+    "@hourly": "0 * * * *",
+}
+
+# Lowest and highest values of each schedule field. For days of the
+# week, both 0 and 7 are Sunday.
+SCHEDULE_RANGES: dict = {
+    "minute": (0, 59),
+    "hour": (0, 23),
+    "day_of_month": (1, 31),
+    "month": (1, 12),
+    "day_of_week": (0, 7),
+}
+
+
+def parse_schedule_field(value: str, field_name: str) -> set:
+    """Return the set of numbers a schedule field matches.
+
+    Fields are comma-separated lists of `*`, numbers, or ranges (e.g.,
+    `3-6`), each with an optional step (e.g., `*/15`). Names are
+    accepted where cron accepts them (see `schedule_names_to_numbers`).
+
+    Raises `ValueError` if the field is invalid.
+    """
+    low, high = SCHEDULE_RANGES[field_name]
+    numbers: set = set()
+    for item in schedule_names_to_numbers(value, field_name).split(","):
+        match: Optional[re.Match] = re.fullmatch(
+            r"(\*|(\d+)(?:-(\d+))?)(?:/(\d+))?", item
+        )
+        if match is None:
+            raise ValueError(f"Invalid {field_name}: {value}.")
+        range_, start, end, step = match.groups()
+        first: int = low if range_ == "*" else int(start)
+        last: int = high if range_ == "*" else int(end or (high if step else start))
+        step_: int = int(step or 1)
+        if not low <= first <= last <= high or step_ == 0:
+            raise ValueError(f"Invalid {field_name}: {value}.")
+        numbers.update(range(first, last + 1, step_))
+    if field_name == "day_of_week" and 7 in numbers:
+        numbers.remove(7)
+        numbers.add(0)
+    return numbers
+
+
+@dataclass
+class ScheduleFields:
+    minute: str
+    hour: str
+    day_of_month: str
+    month: str
+    day_of_week: str
+
 
 @dataclass
 class CronJob:
     schedule: str
     job: str
     description: str
+    line_number: int = field(default=0, compare=False)
+    umask: Optional[int] = None
+    # Interpret Quartz-style `?` day fields (see `CrontabParser`).
+    quartz_marks: bool = field(default=False, compare=False)
+    # The line started with `-`, which tells Vixie cron not to log runs
+    # of the job to syslog. It has no effect on running it by hand.
+    no_log: bool = False
+
+    @property
+    def runs_at_reboot(self) -> bool:
+        return self.schedule == "@reboot"
+
+    @property
+    def schedule_fields(self) -> Optional[ScheduleFields]:
+        """Split the schedule into its five fields.
+
+        This is `None` for shortcuts (e.g., `@daily`), which have no
+        fields, and for incomplete schedules.
+
+        With `quartz_marks`, `?` in the day fields means `*`.
+        """
+        fields: list = self.schedule.split()
+        if len(fields) != 5:
+            return None
+        schedule_fields = ScheduleFields(*fields)
+        if self.quartz_marks and schedule_fields.day_of_month == "?":
+            schedule_fields.day_of_month = "*"
+        if self.quartz_marks and schedule_fields.day_of_week == "?":
+            schedule_fields.day_of_week = "*"
+        return schedule_fields
+
+    def next_run_after(self, now: datetime) -> Optional[datetime]:
+        """Return the next time the job is scheduled, after `now`.
+
+        The computation is done on the wall clock of `now`, and ignores
+        DST transitions; pass a UTC time for unambiguous results.
+
+        Like cron, if both the day of the month and the day of the week
+        are restricted, a day matching either one matches.
+
+        Returns `None` for `@reboot` jobs, unknown shortcuts, invalid
+        schedules, and schedules that never match (e.g., February 30).
+        """
+        fields: Optional[ScheduleFields] = self.schedule_fields
+        if self.schedule in SCHEDULE_SHORTCUTS:
+            fields = ScheduleFields(*SCHEDULE_SHORTCUTS[self.schedule].split())
+        if fields is None:
+            return None
+        try:
+            minutes: set = parse_schedule_field(fields.minute, "minute")
+            hours: set = parse_schedule_field(fields.hour, "hour")
+            days_of_month: set = parse_schedule_field(
+                fields.day_of_month, "day_of_month"
+            )
+            months: set = parse_schedule_field(fields.month, "month")
+            days_of_week: set = parse_schedule_field(fields.day_of_week, "day_of_week")
+        except ValueError:
+            return None
+        any_day: bool = "*" in (fields.day_of_month[:1], fields.day_of_week[:1])
+
+        def is_day_matching(date: datetime) -> bool:
+            day_of_month: bool = date.day in days_of_month
+            day_of_week: bool = (date.weekday() + 1) % 7 in days_of_week
+            if any_day:
+                return day_of_month and day_of_week
+            return day_of_month or day_of_week
+
+        candidate: datetime = now.replace(second=0, microsecond=0)
+        candidate += timedelta(minutes=1)
+        # The rarest day that exists is February 29, which can be up to
+        # 8 years apart (e.g., 2096 and 2104).
+        last_year: int = candidate.year + 8
+        while candidate.year <= last_year:
+            if candidate.month not in months:
+                candidate = candidate.replace(day=1, hour=0, minute=0)
+                candidate = (candidate + timedelta(days=32)).replace(day=1)
+            elif not is_day_matching(candidate):
+                candidate = candidate.replace(hour=0, minute=0)
+                candidate += timedelta(days=1)
+            elif candidate.hour not in hours:
+                candidate = candidate.replace(minute=0) + timedelta(hours=1)
+            elif candidate.minute not in minutes:
+                candidate += timedelta(minutes=1)
+            else:
+                return candidate
+        return None
+
+    def content_fingerprint(
+        self, algo: str = "djb2", include_schedule: bool = False
+    ) -> int:
+        """Hash the job's command, and optionally its schedule.
+
+        Unlike job numbers, the fingerprint does not depend on where the
+        job is in the crontab: identical jobs have identical
+        fingerprints.
+        """
+        content: str = self.job
+        if include_schedule:
+            content = f"{self.schedule} {content}"
+        return HASH_FUNCTIONS[algo](content.encode())
+
+    def fingerprint_hex(
+        self, algo: str = "djb2", include_schedule: bool = False
+    ) -> str:
+        """Return the content fingerprint in its canonical form.
+
+        See `format_fingerprint()`.
+        """
+        return format_fingerprint(self.content_fingerprint(algo, include_schedule))
 
 
 @dataclass
 class Variable:
+    # Variables that have a meaning for cron itself.
+    SPECIAL_IDENTIFIERS: ClassVar[frozenset] = frozenset(
+        {"SHELL", "HOME", "PATH", "MAILTO", "CRON_TZ"}
+    )
+
     identifier: str
     value: str
+    line_number: int = field(default=0, compare=False)
 
     @property
     def declaration(self) -> str:
         return f"{self.identifier}={self.value}"
 
+    @property
+    def is_special(self) -> bool:
+        return self.identifier in self.SPECIAL_IDENTIFIERS
+
 
 @dataclass
 class Comment:
     value: str
+    line_number: int = field(default=0, compare=False)
 
 
 @dataclass
 class Unknown:
     value: str
+    hint: str = ""
+    line_number: int = field(default=0, compare=False)
+
+
+@dataclass
+class Blank:
+    line_number: int = field(default=0, compare=False)
+
+
+@dataclass
+class RawToken:
+    token: object
+    raw: str
 
 
 class CrontabParser:
+    def __init__(
+        self,
+        umask_directives: bool = False,
+        extra_comment_prefixes: tuple = (),
+        keep_blank_lines: bool = False,
+        quartz_marks: bool = False,
+        trim_commands: bool = True,
+    ) -> None:
+        """Create a parser.
+
+        `umask_directives` enables CronRunner-specific `#@umask <mode>`
+        comments. Jobs following such a comment get run with the given
+        umask (see `Crontab.run()`).
+
+        `extra_comment_prefixes` are treated like `#`, to ease migrating
+        from schedulers that use other comment styles (e.g., `;`,
+        `REM`). Cron itself only knows `#`.
+
+        `keep_blank_lines` makes empty lines `Blank` tokens instead of
+        skipping them, so that `parse_lossless()` keeps every line of
+        the source.
+
+        `quartz_marks` makes jobs read `?` in the day fields as `*`, like
+        Quartz does, when interpreting schedules (e.g., in
+        `CronJob.next_run_after()`). Cron does not know `?`, and the
+        schedule is passed along as is.
+
+        Without `trim_commands`, commands are kept verbatim: only the
+        single space or tab separating them from the schedule is
+        dropped. Beware that whitespace can change what commands do
+        (e.g., a trailing space escaped by a backslash).
+        """
+        self.umask_directives: bool = umask_directives
+        self.extra_comment_prefixes: tuple = tuple(extra_comment_prefixes)
+        self.keep_blank_lines: bool = keep_blank_lines
+        self.quartz_marks: bool = quartz_marks
+        self.trim_commands: bool = trim_commands
+        self._umask: Optional[int] = None
+
     def parse(self, crontab: str) -> list:
+        return [raw_token.token for raw_token in self.parse_lossless(crontab)]
+
+    def parse_line(self, line: str) -> Optional[object]:
+        """Parse a single line, as if it were a whole crontab.
+
+        This is useful to validate lines one at a time (e.g., in an
+        editor). There is no context: jobs get no description, and are
+        on line 1. Blank lines give `None` (or `Blank`, see
+        `keep_blank_lines`).
+        """
+        self._umask = None
+        return self._make_token(line.strip(), [], 1, line)
+
+    def parse_lossless(self, crontab: str) -> list:
+        """Parse crontab, keeping the raw source line of each token.
+
+        Tokens are normalized (e.g., lines are stripped), so the crontab
+        cannot be reconstructed from them alone. Keeping the raw lines
+        lets editing tools rewrite only the lines they change, and leave
+        the rest byte-for-byte identical. Blank lines are only kept with
+        `keep_blank_lines`.
+
+        Lines ending with a backslash are continued on the next line
+        (see `_join_continued_lines()`). The raw line of such tokens
+        spans all the physical lines, newlines included.
+        """
+        raw_tokens: list = []
         tokens: list = []
-        line: str
-        for line in crontab.splitlines():
-            line = line.strip()
-            if self._is_job(line):
-                schedule, job = self._split_schedule_and_job(line)
-                description: str = ""
-                if self._is_previous_token_a_description_comment(tokens):
-                    description_comment: str = tokens[-1].value
-                    description = description_comment[2:].lstrip()
-                tokens.append(CronJob(schedule, job, description))
-            elif self._is_variable(line):
-                identifier, value = self._split_identifier_and_value(line)
-                tokens.append(Variable(identifier, value))
-            elif self._is_comment(line):
-                tokens.append(Comment(line))
-            elif not line:
-                pass
+        self._umask = None
+        line_number: int
+        raw: str
+        for line_number, raw in self._join_continued_lines(crontab):
+            untrimmed: str = re.sub(r"\\\n", "", raw)
+            token: object = self._make_token(
+                untrimmed.strip(), tokens, line_number, untrimmed
+            )
+            if token is None:
+                continue
+            tokens.append(token)
+            raw_tokens.append(RawToken(token, raw))
+
+        return raw_tokens
+
+    @classmethod
+    def _join_continued_lines(cls, crontab: str) -> list:
+        """Join lines ending with a backslash with the following line.
+
+        Returns `(line_number, raw)` pairs, where `line_number` is the
+        number of the first physical line, and `raw` contains all the
+        physical lines joined with newlines.
+
+        Like in `sh`, an escaped backslash (`\\\\`) does not continue
+        the line, and neither does a backslash ending a comment. A
+        backslash ending the last line is kept as is.
+        """
+        lines: list = []
+        is_continued: bool = False
+        for line_number, raw in enumerate(crontab.splitlines(), start=1):
+            if is_continued:
+                first_line_number, previous = lines[-1]
+                lines[-1] = (first_line_number, f"{previous}\n{raw}")
             else:
-                tokens.append(Unknown(line))
+                lines.append((line_number, raw))
+            is_continued = cls._is_continued(lines[-1][1])
+        return lines
+
+    @staticmethod
+    def _is_continued(raw: str) -> bool:
+        if raw.lstrip().startswith("#"):
+            return False
+        trailing_backslashes: int = len(raw) - len(raw.rstrip("\\"))
+        return trailing_backslashes % 2 == 1
+
+    def _make_token(
+        self, line: str, tokens: list, line_number: int, untrimmed: str = ""
+    ) -> object:
+        """Make a token out of a stripped line, or `None` if it's empty.
 
-        return tokens
+        Empty lines make `Blank` tokens instead, if `keep_blank_lines`.
+
+        `tokens` are the tokens preceding the line, used for context.
+        `untrimmed` is the line before stripping, which jobs are split
+        from without `trim_commands`.
+        """
+        if self._is_job(line):
+            no_log: bool = line.startswith("-")
+            schedule, job = self._split_schedule_and_job(
+                (line if self.trim_commands else untrimmed.lstrip())[no_log:],
+                self.trim_commands,
+            )
+            if not job.strip():
+                return Unknown(line, "job has no command", line_number)
+            description: str = ""
+            if self._is_previous_token_a_description_comment(tokens):
+                description_comment: str = self._previous_token(tokens).value
+                description = description_comment[2:].lstrip()
+            return CronJob(
+                schedule,
+                job,
+                description,
+                line_number,
+                self._umask,
+                self.quartz_marks,
+                no_log,
+            )
+        elif self._is_extra_comment(line):
+            return Comment(line, line_number)
+        elif self._is_variable(line):
+            identifier, value = self._split_identifier_and_value(line)
+            return Variable(identifier, value, line_number)
+        elif self._is_shebang(line):
+            return Unknown(line, "shebang lines are not valid in crontabs", line_number)
+        elif self.umask_directives and self._is_umask_directive(line):
+            try:
+                self._umask = int(line.split()[1], 8)
+            except (IndexError, ValueError):
+                return Unknown(
+                    line, "umask must be an octal mode, e.g., 022", line_number
+                )
+            return Comment(line, line_number)
+        elif self._is_comment(line):
+            return Comment(line, line_number)
+        elif not line:
+            return Blank(line_number) if self.keep_blank_lines else None
+        else:
+            return Unknown(line, line_number=line_number)
 
     @staticmethod
     def _is_job(line: str) -> bool:
-        return bool(re.match(r"(\d+|\*|@)", line))
+        """Return whether the line is a job.
+
+        Jobs may start with `-` (e.g., `-@daily`), which Vixie cron
+        reads as "do not log this job" (see `CronJob.no_log`).
+        """
+        return bool(re.match(r"-?(\d+|\*|@)", line))
 
     @staticmethod
-    def _split_schedule_and_job(line: str) -> tuple:
+    def _split_schedule_and_job(line: str, trim: bool = True) -> tuple:
         """Split schedule and job parts of a job line.
 
         This is a naive splitter that assumes a schedule consists of
         either one element if it is a shortcut (e.g., @daily), or five
-        elements if not (e.g., * * * * *, 0 12 * * *, etc.).
+        elements if not (e.g., * * * * *, 0 12 * * *, etc.). `@every`
+        is the exception: its duration is part of the schedule (e.g.,
+        @every 1h30m), like in cron-like tools that support it.
+
+        Elements may be separated by any amount of spaces or tabs. The
+        schedule is normalized to single spaces.
 
         Once the appropriate number of elements is consumed (i.e., the
         schedule is consumed), it considers the rest to be the job
-        itself.
+        itself. The job is stripped, unless `trim` is false, in which
+        case only the one character separating it from the schedule is
+        dropped.
         """
-        schedule_length: int = 1 if line.startswith("@") else 5
+        schedule_length: int = 5
+        if re.match(r"@every\s", line):
+            schedule_length = 2
+        elif line.startswith("@"):
+            schedule_length = 1
         schedule: list = []
-        job: list = []
-        i: int = 0
-        for element in line.split(" "):
-            # Schedule.
-            if i < schedule_length:
-                schedule.append(element)
-                if element:
-                    i += 1
-            # Job.
-            else:
-                job.append(element)
-        schedule: str = " ".join(schedule).strip()
-        job: str = " ".join(job).strip()
-        return schedule, job
+        job: str = line
+        while job and len(schedule) < schedule_length:
+            element, job = re.match(r"(\S+)\s?(.*)", job).groups()
+            schedule.append(element)
+            if len(schedule) < schedule_length:
+                job = job.lstrip()
+        return " ".join(schedule), job.strip() if trim else job
 
     @staticmethod
     def _is_previous_token_a_description_comment(tokens: list) -> bool:
@@ -145,13 +625,19 @@ class CrontabParser:
         give a human-readable description to sometimes obscure commands.
 
         This is CronRunner specific, and has nothing to do with Cron
-        itself.
+        itself. Blank lines in between are ignored.
         """
-        if not tokens:
-            return False
-        last_token: str = tokens[-1]
+        last_token: object = CrontabParser._previous_token(tokens)
         return isinstance(last_token, Comment) and last_token.value.startswith("##")
 
+    @staticmethod
+    def _previous_token(tokens: list) -> Optional[object]:
+        """Return the last token that is not `Blank`, if any."""
+        for token in reversed(tokens):
+            if not isinstance(token, Blank):
+                return token
+        return None
+
     @staticmethod
     def _is_variable(line: str) -> bool:
         return "=" in line and re.match(r"[a-zA-Z_][a-zA-Z0-9_]*", line)
@@ -161,102 +647,1937 @@ class CrontabParser:
         identifier, value = line.split("=", maxsplit=1)
         return identifier.strip(), value.strip()
 
+    @staticmethod
+    def _is_shebang(line: str) -> bool:
+        """Return whether the line is a shebang (e.g., `#!/bin/bash`).
+
+        Cron would treat it as a comment, but it is almost always a
+        script that was pasted into the crontab by mistake.
+        """
+        return line.startswith("#!")
+
+    @staticmethod
+    def _is_umask_directive(line: str) -> bool:
+        return line == "#@umask" or line.startswith("#@umask ")
+
     @staticmethod
     def _is_comment(line: str) -> bool:
         return line.startswith("#")
 
+    def _is_extra_comment(self, line: str) -> bool:
+        """Return whether the line starts with an extra comment prefix.
+
+        Word prefixes must be followed by a non-word character, so that
+        `REM` does not match `REMOTE=host`.
+        """
+        for prefix in self.extra_comment_prefixes:
+            boundary: str = r"(?!\w)" if re.match(r"\w", prefix[-1:]) else ""
+            if re.match(re.escape(prefix) + boundary, line):
+                return True
+        return False
+
+
+@dataclass
+class ResourceUsage:
+    # CPU time, in seconds.
+    user_time: float
+    system_time: float
+
+
+@dataclass
+class RunResult:
+    # Like `subprocess`, a negative exit code `-N` means the job was
+    # killed by signal `N`.
+    exit_code: int
+    # Only available on Unix.
+    resource_usage: Optional[ResourceUsage] = None
+    # The job was terminated because it ran for too long.
+    timed_out: bool = False
+    # PID of the process started to run the job (i.e., the shell, or the
+    # wrapper if there is one).
+    pid: Optional[int] = None
+    # When the job was started, and when it ended, in local time (see
+    # `_format_timestamp()`).
+    started_at: Optional[datetime] = None
+    ended_at: Optional[datetime] = None
+
+    @property
+    def duration(self) -> Optional[float]:
+        """Return how long the job ran for, in seconds, if known."""
+        if self.started_at is None or self.ended_at is None:
+            return None
+        return (self.ended_at - self.started_at).total_seconds()
+
+
+@dataclass
+class MenuOptions:
+    # Collapse whitespace in commands (see `_collapse_whitespace()`).
+    trim_command: bool = False
+    # Do not list `@reboot` jobs.
+    hide_reboot: bool = False
+    # Right-align job numbers to this width.
+    id_width: int = 0
+    # Prefix jobs with icons (see `_make_job_icons()`).
+    icons: bool = False
+    unicode_icons: bool = True
+    # Show when jobs next run after this time.
+    next_run_from: Optional[datetime] = None
+    # Only list jobs matching this predicate (see `Crontab.filter_jobs()`).
+    job_filter: Optional[Callable] = None
+
+    def lists(self, job: CronJob) -> bool:
+        """Return whether the menu lists `job`."""
+        return self.hidden_by(job) is None
+
+    def hidden_by(self, job: CronJob) -> Optional[str]:
+        """Return what hides `job` from the menu, if anything."""
+        if self.hide_reboot and job.runs_at_reboot:
+            return "--no-reboot"
+        if self.job_filter is not None and not self.job_filter(job):
+            return "the filter"
+        return None
+
 
 class Crontab:
     DEFAULT_SHELL: str = "/bin/sh"
+    DEFAULT_SHELL_ARG: str = "-c"
+    # Shells that take their command with something other than `-c`,
+    # keyed by basename.
+    SHELL_ARGS: dict = {
+        "pwsh": "-Command",
+        "pwsh.exe": "-Command",
+        "powershell": "-Command",
+        "powershell.exe": "-Command",
+        "cmd.exe": "/C",
+    }
+    # Shells that do not speak POSIX `sh`, keyed by basename. Commands
+    # for them are written in their own syntax (see `_declare()`).
+    SHELL_DIALECTS: dict = {
+        "pwsh": "powershell",
+        "pwsh.exe": "powershell",
+        "powershell": "powershell",
+        "powershell.exe": "powershell",
+        "cmd.exe": "cmd",
+    }
+    # Not found in `PATH`, but always available.
+    SHELL_BUILTINS: frozenset = frozenset(
+        """
+        ! ( . : [ { alias break case cd command continue echo eval exec exit
+        export false for getopts if kill local printf pwd read readonly return
+        set shift source test times trap true type ulimit umask unalias unset
+        until wait while
+        """.split()
+    )
+    # Seconds a timed out job is given to exit before being killed.
+    TIMEOUT_GRACE_PERIOD: float = 5
+    # Shells that can parse a command without running it (`-n`).
+    SYNTAX_CHECK_SHELLS: frozenset = frozenset(
+        {"sh", "bash", "dash", "ksh", "mksh", "zsh", "yash"}
+    )
 
-    def __init__(self, nodes: list) -> None:
+    def __init__(self, nodes: list, source: str = "") -> None:
         self.nodes: list = nodes
+        self.source: str = source
         self._shell: str = ""
 
     @property
     def jobs(self) -> list:
         return [node for node in self.nodes if isinstance(node, CronJob)]
 
+    @property
+    def variables(self) -> list:
+        return [node for node in self.nodes if isinstance(node, Variable)]
+
+    @property
+    def scheduled_jobs(self) -> list:
+        """Return jobs that run on a schedule, i.e., not at reboot."""
+        return [job for job in self.jobs if not job.runs_at_reboot]
+
+    @property
+    def unknowns(self) -> list:
+        return [node for node in self.nodes if isinstance(node, Unknown)]
+
     def __bool__(self) -> bool:
         return len(self.jobs) > 0
 
-    def run(self, job: CronJob) -> None:
+    def render_menu(self, options: MenuOptions, writer: TextIO) -> None:
+        """Write the job selection menu to `writer`.
+
+        Jobs are numbered by their position among all jobs, even if some
+        are hidden, so that numbers always select the same job.
+        """
+        for i, job in enumerate(self.jobs):
+            if not options.lists(job):
+                continue
+            job_number: str = _format_job_number(i + 1, options.id_width)
+            if options.icons:
+                job_number += " " + _make_job_icons(job, options.unicode_icons)
+            description: str = f"{job.description} " if job.description else ""
+            schedule: str = _color_attenuate(job.schedule)
+            command: str = job.job
+            if options.trim_command:
+                command = _collapse_whitespace(command)
+            command = _color_attenuate(command) if description else command
+            next_run: str = ""
+            if options.next_run_from is not None:
+                next_run_at: Optional[datetime] = job.next_run_after(
+                    options.next_run_from
+                )
+                if next_run_at is not None:
+                    next_run = " " + _color_attenuate(
+                        f"(next run: {next_run_at:%Y-%m-%d %H:%M})"
+                    )
+            writer.write(f"{job_number} {description}{schedule} {command}{next_run}\n")
+
+    def tokens_to_json(self) -> str:
+        """Serialize every token, in document order, to a JSON array.
+
+        Each token is an object with its fields, and a `type` telling
+        what kind of token it is (`job`, `variable`, `comment`,
+        `unknown`, or `blank`). Unlike the menu, this keeps everything
+        the parser saw, for tools that annotate or rewrite crontabs.
+        """
+        types: dict = {
+            CronJob: "job",
+            Variable: "variable",
+            Comment: "comment",
+            Unknown: "unknown",
+            Blank: "blank",
+        }
+        return json.dumps(
+            [{"type": types[type(node)], **asdict(node)} for node in self.nodes]
+        )
+
+    def filter_jobs(self, predicate: Callable) -> list:
+        """Return the jobs for which `predicate(job)` is true."""
+        return [job for job in self.jobs if predicate(job)]
+
+    def fingerprint_snapshot(self, algo: str = "djb2") -> list:
+        """Return the fingerprints of all jobs, in order.
+
+        Schedules are part of the fingerprints, so that rescheduling a
+        job counts as a change.
+        """
+        return [
+            job.content_fingerprint(algo, include_schedule=True) for job in self.jobs
+        ]
+
+    def duplicate_fingerprints(self, algo: str = "djb2") -> list:
+        """Return the snapshot fingerprints shared by several jobs.
+
+        These are identical jobs, or hash collisions. Either way, the
+        jobs cannot be told apart in snapshots (see `--only-changed`).
+        Fingerprints are listed once each, in order of first appearance.
+        """
+        seen: set = set()
+        duplicates: list = []
+        for fingerprint in self.fingerprint_snapshot(algo):
+            if fingerprint in seen and fingerprint not in duplicates:
+                duplicates.append(fingerprint)
+            seen.add(fingerprint)
+        return duplicates
+
+    def source_line(self, job: CronJob) -> str:
+        """Return the raw crontab line the job was parsed from.
+
+        Line numbers are 1-based and count every physical line of the
+        source, blank lines included, so they match what an editor
+        would show. Continued lines are returned whole, like in
+        `CrontabParser.parse_lossless()`.
+
+        Raises `ValueError` if the job has no line in the source.
+        """
+        if job not in self.nodes:
+            raise ValueError(f"Unknown job: {job}.")
+        for raw_token in CrontabParser().parse_lossless(self.source):
+            if raw_token.token.line_number == job.line_number:
+                return raw_token.raw
+        raise ValueError(f"No source line for job: {job}.")
+
+    def variables_in_scope(self, job: CronJob) -> dict:
+        """Return the variables declared before the job.
+
+        If a variable is declared more than once, the last declaration
+        before the job wins, as it would when running the job.
+        """
+        if job not in self.nodes:
+            raise ValueError(f"Unknown job: {job}.")
+        variables: dict = {}
+        for node in self.nodes:
+            if isinstance(node, Variable):
+                variables[node.identifier] = node.value
+            if node == job:
+                break
+        return variables
+
+    def borrowed_declarations(self, job: CronJob) -> list:
+        """Return declarations of the variables in scope for the job.
+
+        This is what another job needs to run with the same variables
+        (see `run()`).
+        """
+        return [
+            Variable(identifier, value).declaration
+            for identifier, value in self.variables_in_scope(job).items()
+        ]
+
+    def check_command_available(self, job: CronJob) -> Optional[str]:
+        """Return the job's command if it cannot be found in `PATH`.
+
+        Only bare command names are checked (`foo`, not `./foo` or
+        `/bin/foo`), against the `PATH` the job would run with. Shell
+        builtins, and commands that cannot be determined statically
+        (e.g., `$EDITOR`), are assumed to be available.
+        """
+        try:
+            words: list = shlex.split(job.job)
+        except ValueError:  # Unbalanced quotes.
+            return None
+        # Skip leading assignments (`FOO=bar cmd`).
+        words = [word for word in words if not re.match(r"[a-zA-Z_]\w*=", word)]
+        if not words:
+            return None
+        command: str = words[0]
+        if command in self.SHELL_BUILTINS or "/" in command or "$" in command:
+            return None
+        path: Optional[str] = self.variables_in_scope(job).get("PATH")
+        if path is not None:
+            path = os.path.expandvars(_unquote(path))
+        if shutil.which(command, path=path):
+            return None
+        return command
+
+    def resolve_shell(self, job: CronJob) -> str:
+        """Return the shell the job would be run with.
+
+        This is the value of the last `SHELL` variable declared before
+        the job, or the default shell if there is none.
+        """
+        if job not in self.nodes:
+            raise ValueError(f"Unknown job: {job}.")
+        self._shell = self.DEFAULT_SHELL
+        self._extract_variables_and_target_job(job)
+        return self._shell
+
+    def exports_variables(self, job: CronJob) -> bool:
+        """Return whether the job's variables reach what it starts.
+
+        POSIX shells get plain assignments, which only change variables
+        that are exported already. Other shells get environment
+        variables (see `_declare()`).
+        """
+        self.resolve_shell(job)
+        return self._dialect() != "posix"
+
+    def check_syntax(self, job: CronJob) -> Optional[RunResult]:
+        """Have the job's shell parse the job, without running it.
+
+        The shell reports syntax errors (e.g., unbalanced quotes) on
+        stderr, and exits with a non-zero code.
+
+        Returns `None` if the shell is not known to support `-n`.
+        """
         if job not in self.nodes:
             raise ValueError(f"Unknown job: {job}.")
         self._shell = self.DEFAULT_SHELL
         out: list = self._extract_variables_and_target_job(job)
-        subprocess.run([self._shell, "-c", ";".join(out)], cwd=Path().home())
+        if _shell_name(self._shell) not in self.SYNTAX_CHECK_SHELLS:
+            return None
+        process: subprocess.CompletedProcess = subprocess.run(
+            [self._shell, "-n", "-c", ";".join(out)]
+        )
+        return RunResult(process.returncode)
+
+    def run(
+        self,
+        job: CronJob,
+        wrapper: Optional[list] = None,
+        profile: Optional[str] = None,
+        cwd: Optional[Path] = None,
+        shell_arg: Optional[str] = None,
+        as_script: bool = False,
+        env_from: Optional[CronJob] = None,
+        timeout: Optional[float] = None,
+        env: Optional[dict] = None,
+    ) -> RunResult:
+        """Run a job through the shell.
+
+        `wrapper` is an optional command prefix the shell invocation is
+        appended to (e.g., `["nice", "-n", "10"]`).
+
+        `profile` is an optional file that is sourced (`. <file>`)
+        before anything else, to make the functions and aliases it
+        defines available to the job. Whether the file can be sourced
+        at all depends on the job's shell.
+
+        `cwd` is the directory the job runs in. It defaults to the home
+        directory, which is where cron runs jobs.
+
+        If the job has a umask (see `CrontabParser`), it is applied for
+        the duration of the run. This is only meaningful on Unix.
+
+        `shell_arg` is the flag passing the command to the shell. If not
+        given, it is guessed from the shell (see `shell_arg_for()`).
+
+        `as_script` writes the command to a temporary script file that
+        is passed to the shell, instead of passing the command inline
+        (`shell_arg` is then unused). This sidesteps any length limit
+        on arguments. The file is removed once the job has run.
+
+        `env_from` is another job whose variables are declared before
+        the job's own, so that the job can reuse them. The job's own
+        variables take precedence, and only they can change the shell.
+
+        `timeout` is the number of seconds the job may run for. Past
+        that, it is sent `SIGTERM`, and then `SIGKILL` if it is still
+        running after `TIMEOUT_GRACE_PERIOD`. On Unix, a job with a
+        timeout runs in a session of its own, so that the signals reach
+        every process it started, not only the shell. The job then has
+        no controlling terminal.
+
+        `env` are environment variables added to CronRunner's own for
+        the job (e.g., `COLUMNS`). Variables declared in the crontab are
+        set after, and win.
+
+        On Unix, the result includes the CPU time used by the job.
+        """
+        if job not in self.nodes:
+            raise ValueError(f"Unknown job: {job}.")
+        self._shell = self.DEFAULT_SHELL
+        out: list = self._extract_variables_and_target_job(job)
+        if env_from is not None:
+            out[:0] = [
+                self._declare(Variable(identifier, value))
+                for identifier, value in self.variables_in_scope(env_from).items()
+            ]
+        if profile is not None:
+            out.insert(0, self._source(profile))
+        script: Optional[str] = None
+        shell_command: list
+        if as_script:
+            script = self._write_script(out, self._script_suffix())
+            shell_command = [self._shell, script]
+        else:
+            if shell_arg is None:
+                shell_arg = self.shell_arg_for(self._shell)
+            shell_command = [self._shell, shell_arg, self._separator().join(out)]
+        previous_umask: Optional[int] = None
+        if job.umask is not None:
+            previous_umask = os.umask(job.umask)
+        usage_before: Optional[ResourceUsage] = self._children_resource_usage()
+        timed_out: bool = False
+        started_at: datetime = datetime.now().astimezone()
+        try:
+            popen_kwargs: dict = {"cwd": cwd if cwd is not None else Path().home()}
+            if env is not None:
+                popen_kwargs["env"] = {**os.environ, **env}
+            if timeout is not None and hasattr(os, "killpg"):
+                popen_kwargs["start_new_session"] = True
+            process: subprocess.Popen = subprocess.Popen(
+                [*(wrapper or []), *shell_command], **popen_kwargs
+            )
+            try:
+                exit_code: int = process.wait(timeout)
+            except subprocess.TimeoutExpired:
+                exit_code = self._terminate(process)
+                timed_out = True
+            except BaseException:  # E.g., `KeyboardInterrupt`.
+                self._signal_job(process, "kill")
+                process.wait()
+                raise
+        finally:
+            if previous_umask is not None:
+                os.umask(previous_umask)
+            if script is not None:
+                os.remove(script)
+        ended_at: datetime = datetime.now().astimezone()
+        usage_after: Optional[ResourceUsage] = self._children_resource_usage()
+        resource_usage: Optional[ResourceUsage] = None
+        if usage_before is not None and usage_after is not None:
+            resource_usage = ResourceUsage(
+                user_time=usage_after.user_time - usage_before.user_time,
+                system_time=usage_after.system_time - usage_before.system_time,
+            )
+        return RunResult(
+            exit_code, resource_usage, timed_out, process.pid, started_at, ended_at
+        )
+
+    @classmethod
+    def _terminate(cls, process: subprocess.Popen) -> int:
+        """Terminate the job, killing it if it does not exit."""
+        cls._signal_job(process, "terminate")
+        try:
+            return process.wait(cls.TIMEOUT_GRACE_PERIOD)
+        except subprocess.TimeoutExpired:
+            cls._signal_job(process, "kill")
+            return process.wait()
+
+    @staticmethod
+    def _signal_job(process: subprocess.Popen, action: str) -> None:
+        """Terminate or kill the job, with `SIGTERM` or `SIGKILL`.
+
+        If the job has a session of its own (see `run()`), the whole
+        process group is signaled, so that the processes started by the
+        shell go too. Otherwise, only the shell is.
+        """
+        if not hasattr(os, "killpg"):  # Windows.
+            getattr(process, action)()
+            return
+        sig: int = signal.SIGTERM if action == "terminate" else signal.SIGKILL
+        try:
+            if os.getpgid(process.pid) == process.pid:
+                os.killpg(process.pid, sig)
+            else:
+                process.send_signal(sig)
+        except ProcessLookupError:  # Already exited, and reaped.
+            pass
+
+    @staticmethod
+    def _children_resource_usage() -> Optional[ResourceUsage]:
+        """Return the CPU time used by terminated child processes."""
+        if resource is None:
+            return None
+        usage = resource.getrusage(resource.RUSAGE_CHILDREN)
+        return ResourceUsage(usage.ru_utime, usage.ru_stime)
+
+    @staticmethod
+    def _write_script(lines: list, suffix: str = ".sh") -> str:
+        with tempfile.NamedTemporaryFile(
+            "w", prefix="cronrunner-", suffix=suffix, delete=False
+        ) as script:
+            script.write("\n".join(lines) + "\n")
+        return script.name
+
+    @classmethod
+    def shell_arg_for(cls, shell: str) -> str:
+        """Return the flag a shell takes its command with.
+
+        This is `-c` for virtually every shell, but some (e.g.,
+        PowerShell) use a different flag.
+        """
+        return cls.SHELL_ARGS.get(_shell_name(shell), cls.DEFAULT_SHELL_ARG)
 
     def _extract_variables_and_target_job(self, job: CronJob) -> list:
-        out: list = []
+        variables: list = []
         for node in self.nodes:
             if isinstance(node, Variable):
                 self._detect_shell_change(node)
-                out.append(node.declaration)
+                variables.append(node)
             if node == job:
-                out.append(node.job)
                 break  # Variables coming after the job are not used.
-        return out
+        # The shell is only known once all variables are, and it decides
+        # how they are declared.
+        return [*(self._declare(variable) for variable in variables), job.job]
+
+    def _dialect(self) -> str:
+        return self.SHELL_DIALECTS.get(_shell_name(self._shell), "posix")
+
+    def _declare(self, variable: Variable) -> str:
+        """Return the variable's declaration, in the shell's syntax.
+
+        POSIX shells get the variable as written in the crontab. Other
+        shells get the value with its quotes removed, like cron would,
+        and quoted again their way.
+        """
+        dialect: str = self._dialect()
+        value: str = _unquote(variable.value)
+        if dialect == "powershell":
+            value = value.replace("'", "''")
+            return f"$env:{variable.identifier}='{value}'"
+        if dialect == "cmd":
+            return f'set "{variable.identifier}={value}"'
+        return variable.declaration
+
+    def _source(self, path: str) -> str:
+        """Return the command sourcing a file, in the shell's syntax."""
+        dialect: str = self._dialect()
+        if dialect == "powershell":
+            return ". '{}'".format(path.replace("'", "''"))
+        if dialect == "cmd":
+            return f'call "{path}"'
+        return f". {shlex.quote(path)}"
+
+    def _separator(self) -> str:
+        return "&" if self._dialect() == "cmd" else ";"
+
+    def _script_suffix(self) -> str:
+        # PowerShell and `cmd.exe` refuse to run scripts without them.
+        return {"powershell": ".ps1", "cmd": ".cmd"}.get(self._dialect(), ".sh")
 
     def _detect_shell_change(self, variable: Variable) -> None:
         if variable.identifier == "SHELL":
             self._shell = variable.value
 
 
-def get_crontab() -> Crontab:
-    crontab: str = CrontabReader().read()
-    nodes: list = CrontabParser().parse(crontab)
-    return Crontab(nodes)
+def _shell_name(shell: str) -> str:
+    """Return the basename of a shell, even given a Windows path."""
+    return PureWindowsPath(shell).name
+
+
+def get_crontab(
+    parser: Optional[CrontabParser] = None, user: Optional[str] = None
+) -> Crontab:
+    return get_crontab_from_source(CrontabReader(user), parser)
+
+
+def get_crontab_from_source(
+    source: object, parser: Optional[CrontabParser] = None
+) -> Crontab:
+    """Make a `Crontab` out of any object with a `read() -> str` method.
+
+    `CrontabReader` reads the crontab of the current user, but any other
+    source will do (e.g., a stub in tests). Sources signal failure by
+    raising `CrontabReadError`.
+    """
+    return get_crontab_from_string(source.read(), parser)
+
+
+def get_crontab_from_string(
+    crontab: str, parser: Optional[CrontabParser] = None
+) -> Crontab:
+    crontab = CrontabReader.normalize(crontab)
+    nodes: list = (parser or CrontabParser()).parse(crontab)
+    return Crontab(nodes, crontab)
+
+
+def get_crontab_from_file(
+    path: Path, parser: Optional[CrontabParser] = None
+) -> Crontab:
+    """Make a `Crontab` out of a file, instead of an installed crontab.
+
+    A path of `-` reads the standard input.
+    """
+    try:
+        if str(path) == "-":
+            return get_crontab_from_string(sys.stdin.read(), parser)
+        return get_crontab_from_string(path.read_text(), parser)
+    except OSError as e:
+        raise CrontabReadError(f"Cannot read {path}: {e.strerror}.")
+
+
+# SGR codes of each style. Styles missing from a theme are not styled.
+THEMES: dict = {
+    "default": {
+        "error": "0;91",
+        "highlight": "0;92",
+        "attenuate": "0;90",
+        "title": "1",
+    },
+    "high-contrast": {
+        "error": "1;97;41",
+        "highlight": "1;93",
+        "attenuate": "0;37",
+        "title": "1;4",
+    },
+    "mono": {},
+}
+
+_palette: dict = THEMES["default"]
+
+
+def _set_theme(name: str) -> None:
+    global _palette
+    _palette = THEMES[name]
+
+
+def _resolve_theme(theme: Optional[str], env: dict) -> str:
+    """Return the name of the theme to use.
+
+    `--theme` takes precedence over the `CRONRUNNER_THEME` environment
+    variable. Unknown themes from the environment are ignored.
+    """
+    if theme is not None:
+        return theme
+    theme = env.get("CRONRUNNER_THEME", "default")
+    return theme if theme in THEMES else "default"
+
+
+def _color(style: str, string: str) -> str:
+    code: Optional[str] = _palette.get(style)
+    if code is None:
+        return string
+    return "\033[{}m{}\033[0m".format(code, string)
 
 
 def _color_error(string: str) -> str:
-    return "\033[0;91m{}\033[0m".format(string)
+    return _color("error", string)
 
 
 def _color_highlight(string: str) -> str:
-    return "\033[0;92m{}\033[0m".format(string)
+    return _color("highlight", string)
 
 
 def _color_attenuate(string: str) -> str:
-    return "\033[0;90m{}\033[0m".format(string)
+    return _color("attenuate", string)
+
+
+def _color_title(string: str) -> str:
+    return _color("title", string)
+
+
+def _format_color_test() -> str:
+    return "\n".join(
+        [
+            "error:     " + _color_error("Invalid job number."),
+            "highlight: " + _color_highlight("1."),
+            "attenuate: " + _color_attenuate("* * * * *"),
+            "title:     " + _color_title("Backup database"),
+        ]
+    )
+
+
+def _parse_args(argv: Optional[list]) -> argparse.Namespace:
+    parser = argparse.ArgumentParser(
+        prog="cronrunner", description="Run cron jobs manually."
+    )
+    parser.add_argument(
+        "--locate",
+        metavar="ID",
+        type=int,
+        help="print the crontab line number and source of a job, then exit",
+    )
+    source = parser.add_mutually_exclusive_group()
+    source.add_argument(
+        "-f",
+        "--file",
+        metavar="FILE",
+        type=Path,
+        help="use a crontab file instead of the installed crontab ('-' for stdin)",
+    )
+    source.add_argument(
+        "--user",
+        metavar="NAME",
+        help="use the crontab of another user (requires privileges)",
+    )
+    parser.add_argument(
+        "--check",
+        action="store_true",
+        help="report problems found in the crontab, then exit",
+    )
+    parser.add_argument(
+        "--show-variables",
+        action="store_true",
+        help="list the variables declared in the crontab, then exit",
+    )
+    parser.add_argument(
+        "--tsv",
+        action="store_true",
+        help="print the jobs as tab-separated values (no color), then exit",
+    )
+    parser.add_argument(
+        "--as-yaml",
+        action="store_true",
+        help="print the jobs as YAML (no color), then exit",
+    )
+    parser.add_argument(
+        "--snapshot",
+        metavar="FILE",
+        type=Path,
+        help="write the fingerprints of all jobs to FILE, then exit",
+    )
+    parser.add_argument(
+        "--only-changed",
+        metavar="FILE",
+        type=Path,
+        help="list jobs that changed since the --snapshot FILE, then exit",
+    )
+    parser.add_argument(
+        "--umask-directives",
+        action="store_true",
+        help="honor '#@umask <mode>' comments (Unix only)",
+    )
+    parser.add_argument(
+        "--quartz-marks",
+        action="store_true",
+        help="read '?' in day fields as '*' when interpreting schedules",
+    )
+    parser.add_argument(
+        "--no-trim",
+        action="store_true",
+        help="keep whitespace around commands as written (may change how they run)",
+    )
+    parser.add_argument(
+        "--plain",
+        action="store_true",
+        help="write prompts to stderr, keeping stdout clean for the job output",
+    )
+    parser.add_argument(
+        "--nice",
+        metavar="N",
+        type=_nice_value,
+        help="run the job with niceness N (-20 to 19)",
+    )
+    parser.add_argument(
+        "--ionice",
+        action="store_true",
+        help="run the job in the idle I/O scheduling class",
+    )
+    parser.add_argument(
+        "--scope",
+        action="store_true",
+        help="run the job in its own systemd scope, using systemd-run (Linux only)",
+    )
+    parser.add_argument(
+        "--export-termsize",
+        action="store_true",
+        help="export the terminal size to the job as COLUMNS and LINES",
+    )
+    parser.add_argument(
+        "--wrap",
+        metavar="PREFIX",
+        help="prefix runs with a command, e.g., 'flock -n FILE' (or CRONRUNNER_WRAP)",
+    )
+    parser.add_argument(
+        "--as-user",
+        metavar="USER",
+        help="run the job as another user (requires the privilege to do so)",
+    )
+    parser.add_argument(
+        "--as-user-with",
+        choices=["sudo", "su"],
+        default="sudo",
+        help="tool used by --as-user to switch users (default: sudo)",
+    )
+    parser.add_argument(
+        "--trim-command",
+        action="store_true",
+        help="collapse repeated whitespace in commands when listing jobs",
+    )
+    parser.add_argument(
+        "--no-reboot",
+        action="store_true",
+        help="do not list @reboot jobs",
+    )
+    parser.add_argument(
+        "--filter",
+        metavar="QUERY",
+        help="only list jobs whose command or description contains QUERY",
+    )
+    parser.add_argument(
+        "--match-schedule",
+        metavar="PATTERN",
+        help="only list jobs whose schedule contains PATTERN, e.g., '@daily'",
+    )
+    parser.add_argument(
+        "--regex",
+        action="store_true",
+        help="treat the --filter query as a regular expression",
+    )
+    parser.add_argument(
+        "--id-width",
+        metavar="N",
+        type=int,
+        default=0,
+        help="right-align job numbers in the menu to a width of N",
+    )
+    parser.add_argument(
+        "--next-run",
+        action="store_true",
+        help="show when jobs are next scheduled to run in the menu",
+    )
+    parser.add_argument(
+        "--theme",
+        choices=sorted(THEMES),
+        help="color theme (or set CRONRUNNER_THEME, default: default)",
+    )
+    parser.add_argument(
+        "--menu-icons",
+        action="store_true",
+        help="mark jobs with a description (📝/D) or that run at reboot (⟳/R)",
+    )
+    parser.add_argument(
+        "--quiet",
+        action="store_true",
+        help="do not print advisory notices before running a job",
+    )
+    parser.add_argument(
+        "--export-env",
+        metavar="ID",
+        type=int,
+        help="print the variables in scope for a job as shell exports, then exit",
+    )
+    parser.add_argument(
+        "--hash",
+        metavar="ID",
+        type=int,
+        help="print the content hash of a job, then exit",
+    )
+    parser.add_argument(
+        "--hash-algo",
+        choices=sorted(HASH_FUNCTIONS),
+        default="djb2",
+        help="hash algorithm used for fingerprints (default: djb2)",
+    )
+    parser.add_argument(
+        "--hash-schedule",
+        action="store_true",
+        help="include the schedule in the hash computed by --hash",
+    )
+    parser.add_argument(
+        "--prompt",
+        metavar="STR",
+        help="override the selection prompt (or set CRONRUNNER_PROMPT)",
+    )
+    parser.add_argument(
+        "--source",
+        metavar="FILE",
+        type=_absolute_path,
+        help="source FILE (e.g., a shell profile) before running the job",
+    )
+    parser.add_argument(
+        "--shell-arg",
+        metavar="FLAG",
+        help="flag passing the command to the shell (default: guessed, e.g., -c)",
+    )
+    parser.add_argument(
+        "--as-script",
+        action="store_true",
+        help="pass the command to the shell as a temporary script file",
+    )
+    parser.add_argument(
+        "--syntax-check",
+        action="store_true",
+        help="check the shell syntax of the selected job instead of running it",
+    )
+    parser.add_argument(
+        "--interactive",
+        action="store_true",
+        help="show the menu again after a job has run, until nothing is selected",
+    )
+    parser.add_argument(
+        "--env-from-job",
+        metavar="ID",
+        type=int,
+        help="also declare the variables in scope for job ID before running",
+    )
+    parser.add_argument(
+        "--banner",
+        action="store_true",
+        help="print the description of the job before running it",
+    )
+    parser.add_argument(
+        "--single-instance",
+        action="store_true",
+        help="refuse to run a job that is already running (Unix only)",
+    )
+    parser.add_argument(
+        "--on-exit",
+        metavar="CODE=MESSAGE",
+        type=_exit_message,
+        action="append",
+        default=[],
+        help="print MESSAGE after the job exits with CODE (repeatable)",
+    )
+    parser.add_argument(
+        "--timeout",
+        metavar="SECONDS",
+        type=float,
+        help="terminate the job if it runs for longer than SECONDS",
+    )
+    parser.add_argument(
+        "--env-diff",
+        action="store_true",
+        help="print how the job's environment differs from the current one",
+    )
+    parser.add_argument(
+        "--stats",
+        action="store_true",
+        help="print the CPU time used by the job after it has run (Unix only)",
+    )
+    parser.add_argument(
+        "--audit-log",
+        metavar="FILE",
+        type=Path,
+        help="append a line with the outcome of the run to FILE",
+    )
+    parser.add_argument(
+        "--run-if",
+        metavar="CONDITION",
+        help="only run the job if the shell command CONDITION succeeds",
+    )
+    parser.add_argument("--color-test", action="store_true", help=argparse.SUPPRESS)
+    cwd = parser.add_mutually_exclusive_group()
+    cwd.add_argument(
+        "--cwd-home",
+        dest="cwd_current",
+        action="store_false",
+        default=False,
+        help="run the job in the home directory, like cron (default)",
+    )
+    cwd.add_argument(
+        "--cwd-current",
+        dest="cwd_current",
+        action="store_true",
+        help="run the job in the current directory instead of the home directory",
+    )
+    return parser.parse_args(argv)
+
+
+def _absolute_path(value: str) -> str:
+    # Jobs run in the home directory, not in the current one.
+    return os.path.abspath(os.path.expanduser(value))
+
+
+def _nice_value(value: str) -> int:
+    niceness: int = int(value)
+    if not -20 <= niceness <= 19:
+        raise argparse.ArgumentTypeError(
+            f"must be between -20 and 19, got {niceness}"
+        )
+    return niceness
+
+
+def _make_priority_wrapper(nice: Optional[int], ionice: bool) -> list:
+    """Build a command prefix lowering the priority of the job.
+
+    Tools that are not available on the system are skipped with a
+    warning, and the job runs with default priority instead.
+    """
+    wrapper: list = []
+    if nice is not None:
+        if shutil.which("nice"):
+            wrapper += ["nice", "-n", str(nice)]
+        else:
+            print(_color_error("Cannot find 'nice', ignoring --nice."))
+    if ionice:
+        if shutil.which("ionice"):
+            wrapper += ["ionice", "-c3"]
+        else:
+            print(_color_error("Cannot find 'ionice', ignoring --ionice."))
+    return wrapper
+
+
+def _make_scope_wrapper(description: str = "") -> list:
+    """Build a command prefix running the job in a systemd scope.
+
+    The job gets its own cgroup in the user's service manager. Unlike
+    with `systemd-run`'s services, a scope's process is started by
+    `systemd-run` itself, so the job keeps the environment (`HOME`,
+    variables, etc.) and working directory it would have had otherwise.
+    """
+    wrapper: list = ["systemd-run", "--user", "--scope", "--quiet"]
+    if description:
+        wrapper.append(f"--description={description}")
+    return [*wrapper, "--"]
+
+
+def _make_user_wrapper(wrap: Optional[str], env: dict) -> list:
+    """Split the user's command prefix into arguments.
+
+    `--wrap` takes precedence over the `CRONRUNNER_WRAP` environment
+    variable. The prefix is split like a shell would (e.g., `flock -n
+    '/tmp/my lock'`), but is not otherwise interpreted by a shell.
+
+    Raises `ValueError` if the quoting is invalid.
+    """
+    if wrap is None:
+        wrap = env.get("CRONRUNNER_WRAP", "")
+    return shlex.split(wrap)
+
 
+def _make_user_switch_wrapper(user: str, method: str = "sudo") -> list:
+    """Build a command prefix running the job as another user.
 
-def main() -> int:
+    This requires the privilege to run commands as that user, and
+    `sudo` or `su` may ask for a password. With `su`, the user's login
+    shell is used to `exec` the job's shell. This relies on util-linux
+    `su`, which passes the arguments following the user name on to the
+    shell; other implementations (e.g., BusyBox) may not.
+
+    Raises `ValueError` if the user name is invalid.
+    """
+    if not re.fullmatch(r"[A-Za-z_][A-Za-z0-9_.-]*\$?", user):
+        raise ValueError(f"Invalid user name: {user}.")
+    if method == "su":
+        return ["su", "-c", 'exec "$0" "$@"', "--", user]
+    return ["sudo", "-u", user, "--"]
+
+
+def _collapse_whitespace(command: str) -> str:
+    """Collapse runs of spaces and tabs into a single space.
+
+    This is for display only. Whitespace inside quotes or escaped with
+    a backslash is part of an argument and is left untouched.
+    """
+    collapsed: list = []
+    quote: str = ""
+    escaped: bool = False
+    in_separator: bool = False
+    for char in command:
+        if escaped:
+            escaped = False
+        elif quote:
+            if char == quote:
+                quote = ""
+            elif char == "\\" and quote == '"':
+                escaped = True
+        elif char in " \t":
+            if not in_separator:
+                collapsed.append(" ")
+            in_separator = True
+            continue
+        elif char == "\\":
+            escaped = True
+        elif char in "'\"":
+            quote = char
+        in_separator = False
+        collapsed.append(char)
+    return "".join(collapsed)
+
+
+def _make_shell_notice(job_shell: str, user_shell: str) -> str:
+    """Return a notice if the job's shell differs from the user's.
+
+    An empty string means there is nothing worth mentioning, either
+    because the shells match or because the user's shell is unknown.
+    """
+    if not user_shell or job_shell == user_shell:
+        return ""
+    return _color_attenuate(
+        f"Note: this job runs in {job_shell}, not in your shell ({user_shell})."
+    )
+
+
+def _format_exports(variables: dict) -> str:
+    """Format variables as shell `export` statements, sorted by name.
+
+    Like cron, matching quotes around a value are not part of the value
+    itself. Values are then double-quoted, so that once `eval`'d they
+    are expanded (e.g., `$HOME`) like when the job runs, in which they
+    are shell assignments (see `Crontab.run()`). Single-quoted values
+    are kept as is, as they are not expanded either.
+    """
+    exports: list = []
+    for identifier, value in sorted(variables.items()):
+        if value[:1] == "'" and _unquote(value) != value:
+            value = "'" + _unquote(value).replace("'", "'\\''") + "'"
+        else:
+            value = '"' + re.sub(r'(["\\])', r"\\\1", _unquote(value)) + '"'
+        exports.append(f"export {identifier}={value}")
+    return "\n".join(exports)
+
+
+def _unquote(value: str) -> str:
+    """Remove matching quotes around a variable value, like cron."""
+    if len(value) >= 2 and value[0] == value[-1] and value[0] in "'\"":
+        return value[1:-1]
+    return value
+
+
+def _effective_env(variables: dict, env: dict, exported: bool = False) -> dict:
+    """Return the environment the processes started by a job run in.
+
+    Jobs inherit the environment of CronRunner, in which the variables
+    in scope for the job are declared. POSIX shells only pass on those
+    that were exported already, unless `exported` (see
+    `Crontab.exports_variables()`). Values are not expanded (e.g.,
+    `$HOME` stays as is).
+    """
+    return {
+        **env,
+        **{
+            identifier: _unquote(value)
+            for identifier, value in variables.items()
+            if exported or identifier in env
+        },
+    }
+
+
+def _diff_env(current: dict, effective: dict) -> list:
+    """Return the differences between two environments, by name.
+
+    Changed variables are given as their current value prefixed with
+    `-`, followed by their effective value prefixed with `+`. Variables
+    only in `effective` are prefixed with `+`. `effective` is built on
+    top of `current` (see `_effective_env()`), so it lacks none.
+    """
+    diff: list = []
+    for identifier in sorted(effective.keys()):
+        if current.get(identifier) == effective[identifier]:
+            continue
+        if identifier in current:
+            diff.append(f"-{identifier}={current[identifier]}")
+        diff.append(f"+{identifier}={effective[identifier]}")
+    return diff
+
+
+def _has_irregular_schedule_spacing(raw_line: str, job: CronJob) -> bool:
+    """Return whether the schedule was not separated by single spaces.
+
+    The schedule's elements are read from the raw line itself, with
+    continued lines joined like the parser does. The parser normalizes
+    such schedules, so this is purely cosmetic.
+    """
+    line: str = re.sub(r"\\\n", "", raw_line).lstrip()[job.no_log :]
+    pattern: str = r"\S+" + r"(\s+)\S+" * (len(job.schedule.split()) - 1)
+    separators: tuple = re.match(pattern, line).groups()
+    return any(separator != " " for separator in separators)
+
+
+def _format_variables(variables: list) -> str:
+    return "\n".join(
+        variable.declaration
+        + (" " + _color_attenuate("(special)") if variable.is_special else "")
+        for variable in variables
+    )
+
+
+def _format_resource_usage(resource_usage: ResourceUsage) -> str:
+    return (
+        f"user {resource_usage.user_time:.3f}s, "
+        f"system {resource_usage.system_time:.3f}s"
+    )
+
+
+def _format_tsv(
+    crontab: Crontab, algo: str = "djb2", listed: Optional[Callable] = None
+) -> str:
+    """Format the jobs as tab-separated values, with a header row.
+
+    Tabs and newlines within fields are replaced with spaces, so that
+    every job is exactly one row of four columns. If `listed` is given,
+    only jobs for which `listed(job)` is true are included (see
+    `MenuOptions.lists()`), with the same ids as in the menu.
+    """
+    rows: list = [["id", "fingerprint", "schedule", "command"]]
+    for i, job in enumerate(crontab.jobs):
+        if listed is not None and not listed(job):
+            continue
+        fingerprint: str = job.fingerprint_hex(algo, include_schedule=True)
+        rows.append([str(i + 1), fingerprint, job.schedule, job.job])
+    return "\n".join(
+        "\t".join(re.sub(r"[\t\r\n]", " ", column) for column in row)
+        for row in rows
+    )
+
+
+def _format_yaml(
+    crontab: Crontab, algo: str = "djb2", listed: Optional[Callable] = None
+) -> str:
+    """Format the jobs as a YAML sequence of mappings.
+
+    Strings are double-quoted like in JSON, which YAML accepts, so that
+    commands containing colons, quotes, or newlines need no special
+    handling. Fingerprints are strings too, lest `1e10` read as a
+    number. `listed` is as in `_format_tsv()`.
+    """
+    entries: list = []
+    for i, job in enumerate(crontab.jobs):
+        if listed is not None and not listed(job):
+            continue
+        fingerprint: str = job.fingerprint_hex(algo, include_schedule=True)
+        entries.append(
+            f"- id: {i + 1}\n"
+            f"  fingerprint: {json.dumps(fingerprint)}\n"
+            f"  schedule: {json.dumps(job.schedule)}\n"
+            f"  command: {json.dumps(job.job)}\n"
+            f"  description: {json.dumps(job.description)}"
+        )
+    if not entries:
+        return "[]"
+    return "\n".join(entries)
+
+
+def _write_snapshot(path: Path, fingerprints: list) -> None:
+    path.write_text(
+        "".join(f"{format_fingerprint(fingerprint)}\n" for fingerprint in fingerprints)
+    )
+
+
+def _read_snapshot(path: Path) -> set:
+    """Read fingerprints written by `_write_snapshot()`.
+
+    Raises `ValueError` if the file is not a snapshot.
+    """
+    return {int(line, 16) for line in path.read_text().splitlines() if line}
+
+
+def _warn_duplicate_fingerprints(crontab: Crontab, algo: str = "djb2") -> None:
+    """Warn on stderr if jobs share a fingerprint. This is advisory."""
+    duplicates: list = crontab.duplicate_fingerprints(algo)
+    if duplicates:
+        print(
+            _color_error(
+                "Warning: some jobs share a fingerprint, changes to them may go"
+                f" unnoticed: {', '.join(map(format_fingerprint, duplicates))}."
+            ),
+            file=sys.stderr,
+        )
+
+
+def _changed_jobs(crontab: Crontab, snapshot: set, algo: str = "djb2") -> list:
+    """Return `(job_number, job)` pairs for jobs not in the snapshot.
+
+    Jobs removed since the snapshot was taken cannot be listed, since
+    only their fingerprints are known.
+    """
+    return [
+        (i + 1, job)
+        for i, (job, fingerprint) in enumerate(
+            zip(crontab.jobs, crontab.fingerprint_snapshot(algo))
+        )
+        if fingerprint not in snapshot
+    ]
+
+
+def _exit_message(value: str) -> tuple:
+    """Parse an `--on-exit` value (`<CODE>=<MESSAGE>`)."""
+    code, separator, message = value.partition("=")
+    if not separator or not re.fullmatch(r"-?\d+", code.strip()):
+        raise argparse.ArgumentTypeError(f"expected <CODE>=<MESSAGE>, got {value!r}")
+    return int(code), message
+
+
+def _get_exit_message(exit_messages: list, exit_code: int) -> Optional[str]:
+    """Return the message for the exit code, if any.
+
+    `exit_messages` are `(code, message)` pairs. If a code is given more
+    than once, the last message wins.
+    """
+    return dict(exit_messages).get(exit_code)
+
+
+def _get_user() -> str:
+    try:
+        return getpass.getuser()
+    except (KeyError, OSError):  # No name for the UID (e.g., in containers).
+        return "?"
+
+
+def _format_timestamp(timestamp: datetime) -> str:
+    """Format a timestamp as RFC 3339, e.g., `2024-03-01T12:30:05+01:00`.
+
+    Naive timestamps are assumed to be in local time.
+    """
+    if timestamp.tzinfo is None:
+        timestamp = timestamp.astimezone()
+    return timestamp.isoformat(timespec="seconds")
+
+
+def _format_audit_line(
+    user: str, job_number: int, job: CronJob, result: RunResult, algo: str = "djb2"
+) -> str:
+    """Format a run as a tab-separated line, for `--audit-log`.
+
+    Columns are: start time, user, job number, fingerprint (using
+    `algo`), exit code, duration in seconds, and command. Unknown values
+    are `-`. The command comes last and has its tabs and newlines
+    replaced with spaces, so every run is exactly one line.
+    """
+    duration: Optional[float] = result.duration
+    return "\t".join(
+        [
+            "-" if result.started_at is None else _format_timestamp(result.started_at),
+            user,
+            str(job_number),
+            job.fingerprint_hex(algo, include_schedule=True),
+            str(result.exit_code),
+            "-" if duration is None else f"{duration:.3f}",
+            re.sub(r"[\t\r\n]", " ", job.job),
+        ]
+    )
+
+
+def _append_audit_line(path: Path, line: str) -> None:
+    """Append a line to the audit log, creating it if needed.
+
+    The run already happened, so failing to log it only warns.
+    """
     try:
-        crontab: Crontab = get_crontab()
+        with path.open("a") as f:
+            f.write(f"{line}\n")
+    except OSError as e:
+        print(_color_error(f"Warning: cannot write audit log: {e.strerror}."))
+
+
+def _check_crontab(crontab: Crontab) -> tuple:
+    """Check the crontab for problems.
+
+    Return a tuple of human-readable problems, and notes. Notes are
+    purely advisory.
+    """
+    problems: list = []
+    notes: list = []
+    for unknown in crontab.unknowns:
+        problem: str = f"Unrecognized line: {unknown.value}"
+        if unknown.hint:
+            problem += f" ({unknown.hint})"
+        problems.append(problem)
+    for job in crontab.jobs:
+        command: Optional[str] = crontab.check_command_available(job)
+        if command is not None:
+            problems.append(f"Command not found: {command} (in '{job.job}')")
+        try:
+            raw_line: str = crontab.source_line(job)
+        except ValueError:  # Not parsed from the source.
+            continue
+        if _has_irregular_schedule_spacing(raw_line, job):
+            notes.append(
+                f"Irregular spacing in schedule, read as '{job.schedule}': "
+                f"{raw_line.strip()}"
+            )
+    return problems, notes
+
+
+def _run_condition(condition: str) -> subprocess.CompletedProcess:
+    return subprocess.run([Crontab.DEFAULT_SHELL, "-c", condition], cwd=Path().home())
+
+
+def _is_condition_met(result: subprocess.CompletedProcess) -> bool:
+    return result.returncode == 0
+
+
+def _format_job_number(job_number: int, width: int = 0) -> str:
+    """Format a job number for the menu, right-aligned to `width`.
+
+    A width smaller than the number itself is ignored; numbers are
+    never truncated.
+    """
+    number: str = str(job_number)
+    padding: str = " " * (width - len(number))
+    return padding + _color_highlight(number) + "."
+
+
+def _supports_unicode_icons(env: dict, encoding: Optional[str]) -> bool:
+    return "NO_COLOR" not in env and "utf" in (encoding or "").lower()
+
+
+def _make_job_icons(job: CronJob, unicode: bool) -> str:
+    """Make the menu icons of a job.
+
+    Each icon has a fixed slot, left blank if it does not apply, so that
+    icons line up from one job to the next. Emojis are two columns wide.
+    """
+    if unicode:
+        return "".join(
+            [
+                "📝" if job.description else "  ",
+                "⟳" if job.runs_at_reboot else " ",
+            ]
+        )
+    return "".join(
+        [
+            "D" if job.description else " ",
+            "R" if job.runs_at_reboot else " ",
+        ]
+    )
+
+
+def _make_reboot_note(job: CronJob) -> str:
+    if not job.runs_at_reboot:
+        return ""
+    return _color_attenuate("This is an @reboot job.")
+
+
+def _make_lock_path(job: CronJob, env: dict) -> Path:
+    """Return the path of the file locked while the job runs.
+
+    The file is named after the job's fingerprint, so the same job gets
+    the same lock from any terminal. It lives in `XDG_RUNTIME_DIR` if
+    set, or in a directory of the user's own in the temporary directory
+    (see `_acquire_lock()`), which other users can write to.
+    """
+    directory: Path
+    if env.get("XDG_RUNTIME_DIR"):
+        directory = Path(env["XDG_RUNTIME_DIR"])
+    else:
+        directory = Path(tempfile.gettempdir()) / f"cronrunner-{os.getuid()}"
+    fingerprint: str = job.fingerprint_hex(include_schedule=True)
+    return directory / f"cronrunner-{fingerprint}.lock"
+
+
+def _ensure_private_directory(path: Path) -> None:
+    """Create the directory, or check that it is private to the user.
+
+    Raises `PermissionError` if it is not (Unix only).
+    """
+    try:
+        os.mkdir(path, 0o700)
+    except FileExistsError:
+        pass
+    info: os.stat_result = os.lstat(path)
+    if (
+        not stat.S_ISDIR(info.st_mode)
+        or info.st_uid != os.getuid()
+        or info.st_mode & 0o077
+    ):
+        raise PermissionError(
+            errno.EPERM, f"Lock directory is not private: {path}", str(path)
+        )
+
+
+def _acquire_lock(path: Path) -> TextIO:
+    """Take an exclusive advisory lock on the file (Unix only).
+
+    The lock is held until the returned file is closed. Raises
+    `BlockingIOError` if the lock is already held.
+
+    The directory of the file is created if needed. It must belong to
+    the user, and be private to them, or else other users could take
+    the lock first, or swap the file for a symlink. For the same
+    reason, the file itself is never opened through a symlink.
+    """
+    _ensure_private_directory(path.parent)
+    fd: int = os.open(path, os.O_RDWR | os.O_CREAT | os.O_NOFOLLOW, 0o600)
+    lock: TextIO = os.fdopen(fd, "w")
+    try:
+        fcntl.flock(lock, fcntl.LOCK_EX | fcntl.LOCK_NB)
+    except OSError:
+        lock.close()
+        raise
+    return lock
+
+
+def _get_terminal_size() -> Optional[os.terminal_size]:
+    """Return the size of the terminal stdout is connected to, if any."""
+    try:
+        return os.get_terminal_size(sys.stdout.fileno())
+    except (OSError, ValueError):  # Not a terminal, or not a real file.
+        return None
+
+
+def _make_termsize_env(size: os.terminal_size) -> dict:
+    """Make the variables telling programs the size of the terminal.
+
+    Interactive shells set them, but `sh -c` does not, so jobs that
+    adapt to the terminal width would fall back to their defaults.
+    """
+    return {"COLUMNS": str(size.columns), "LINES": str(size.lines)}
+
+
+def _make_banner(job: CronJob) -> str:
+    """Return a banner giving context about the job being run.
+
+    The banner is the job's description, if it has one; jobs without a
+    description get no banner.
+    """
+    if not job.description:
+        return ""
+    return _color_title(f"== {job.description} ==")
+
+
+def _make_job_filter(query: str, regex: bool = False) -> Callable:
+    """Make a predicate matching jobs whose command or description
+    contains `query`, ignoring case.
+
+    With `regex`, `query` is a regular expression to search for instead.
+    Raises `re.error` if it is invalid.
+    """
+    pattern: re.Pattern = re.compile(query if regex else re.escape(query), re.I)
+
+    def job_filter(job: CronJob) -> bool:
+        return bool(pattern.search(job.job) or pattern.search(job.description))
+
+    return job_filter
+
+
+def _make_schedule_filter(pattern: str) -> Callable:
+    """Make a predicate matching jobs whose schedule contains `pattern`
+    (e.g., `@daily`, or `0 3 `).
+
+    Schedules are matched as the parser normalized them, with fields
+    separated by single spaces.
+    """
+
+    def schedule_filter(job: CronJob) -> bool:
+        return pattern in job.schedule
+
+    return schedule_filter
+
+
+def _combine_filters(job_filters: list) -> Callable:
+    """Make a predicate matching jobs that match all `job_filters`."""
+
+    def combined_filter(job: CronJob) -> bool:
+        return all(job_filter(job) for job_filter in job_filters)
+
+    return combined_filter
+
+
+def _get_job_from_number(crontab: Crontab, job_number: int) -> CronJob:
+    if not 0 < job_number <= len(crontab.jobs):
+        raise ValueError(f"Invalid job number: {job_number}.")
+    return crontab.jobs[job_number - 1]
+
+
+DEFAULT_PROMPT: str = ">>> Select a job to run: "
+
+
+def _resolve_prompt(prompt: Optional[str], env: dict) -> str:
+    """Return the selection prompt to use.
+
+    `--prompt` takes precedence over the `CRONRUNNER_PROMPT` environment
+    variable, which takes precedence over the default prompt.
+    """
+    if prompt is not None:
+        return prompt
+    return env.get("CRONRUNNER_PROMPT", DEFAULT_PROMPT)
+
+
+def _reattach_stdin_to_terminal() -> None:
+    """Read the job number from the terminal once stdin is consumed.
+
+    If there is no terminal (e.g., in a pipeline), stdin is left as is.
+    """
+    try:
+        sys.stdin = open("/dev/tty")
+    except OSError:
+        pass
+
+
+def _get_user_selection(prompt_writer: TextIO, prompt: str = DEFAULT_PROMPT) -> str:
+    prompt_writer.write(prompt)
+    prompt_writer.flush()
+    return input()
+
+
+def _make_args_filter(args: argparse.Namespace) -> Optional[Callable]:
+    """Make a predicate matching jobs that match all filtering options.
+
+    Returns `None` if there are no filtering options. Raises `re.error`
+    if `--filter` is an invalid regex.
+    """
+    job_filters: list = []
+    if args.filter is not None:
+        job_filters.append(_make_job_filter(args.filter, args.regex))
+    if args.match_schedule is not None:
+        job_filters.append(_make_schedule_filter(args.match_schedule))
+    return _combine_filters(job_filters) if job_filters else None
+
+
+def _select_and_run(
+    crontab: Crontab,
+    args: argparse.Namespace,
+    env_from: Optional[CronJob] = None,
+    job_filter: Optional[Callable] = None,
+) -> Optional[int]:
+    """Show the menu, then run the job the user selects.
+
+    `env_from` is passed on to `Crontab.run()`, and `job_filter` to the
+    menu (see `_make_args_filter()`).
+
+    Returns `None` if the user selected nothing, or the exit code of
+    CronRunner otherwise.
+    """
+    menu_options: MenuOptions = MenuOptions(
+        trim_command=args.trim_command,
+        hide_reboot=args.no_reboot,
+        id_width=args.id_width,
+        icons=args.menu_icons,
+        unicode_icons=_supports_unicode_icons(os.environ, sys.stdout.encoding),
+        next_run_from=datetime.now() if args.next_run else None,
+        job_filter=job_filter,
+    )
+
+    crontab.render_menu(menu_options, sys.stdout)
+
+    job_number: str = _get_user_selection(
+        sys.stderr if args.plain else sys.stdout,
+        _resolve_prompt(args.prompt, os.environ),
+    )
+    if not job_number:
+        return None
+    try:
+        job: CronJob = _get_job_from_number(crontab, int(job_number))
+    except ValueError:
+        print(_color_error("Invalid job number."))
+        return 1
+    # Numbers of hidden jobs exist, but must not be run blindly.
+    hidden_by: Optional[str] = menu_options.hidden_by(job)
+    if hidden_by is not None:
+        print(_color_error(f"Job {int(job_number)} is hidden by {hidden_by}."))
+        return 1
+
+    if args.syntax_check:
+        syntax_check: Optional[RunResult] = crontab.check_syntax(job)
+        if syntax_check is None:
+            shell: str = crontab.resolve_shell(job)
+            print(_color_attenuate(f"Cannot check syntax with '{shell}', skipping."))
+            return 0
+        if syntax_check.exit_code != 0:
+            print(_color_error("Syntax error."))
+            return 1
+        print("Syntax OK.")
+        return 0
+
+    if args.run_if is not None and not _is_condition_met(_run_condition(args.run_if)):
+        print(f"Condition not met, skipping job: {args.run_if}")
+        return 0
+
+    try:
+        user_wrapper: list = _make_user_wrapper(args.wrap, os.environ)
+    except ValueError as e:
+        print(_color_error(f"Invalid wrapper: {e}."))
+        return 1
+    wrapper: list = []
+    if args.scope:
+        if shutil.which("systemd-run"):
+            wrapper += _make_scope_wrapper(job.description)
+        else:
+            print(_color_error("Cannot find 'systemd-run', ignoring --scope."))
+    wrapper += [*_make_priority_wrapper(args.nice, args.ionice), *user_wrapper]
+    if args.as_user is not None:
+        if args.as_script:
+            # The script belongs to us, and is private: the other user
+            # could not read it.
+            print(_color_error("--as-user cannot be combined with --as-script."))
+            return 1
+        if not shutil.which(args.as_user_with):
+            print(_color_error(f"Cannot find '{args.as_user_with}'."))
+            return 1
+        try:
+            wrapper += _make_user_switch_wrapper(args.as_user, args.as_user_with)
+        except ValueError as e:
+            print(_color_error(str(e)))
+            return 1
+    if not args.quiet:
+        reboot_note: str = _make_reboot_note(job)
+        if reboot_note:
+            print(reboot_note)
+        missing_command: Optional[str] = crontab.check_command_available(job)
+        if missing_command is not None:
+            print(_color_error(f"Warning: command not found: {missing_command}."))
+        shell_notice: str = _make_shell_notice(
+            crontab.resolve_shell(job), os.environ.get("SHELL", "")
+        )
+        if shell_notice:
+            print(shell_notice)
+    if args.env_diff:
+        variables: dict = crontab.variables_in_scope(job)
+        if env_from is not None:
+            variables = {**crontab.variables_in_scope(env_from), **variables}
+        env_diff: list = _diff_env(
+            dict(os.environ),
+            _effective_env(
+                variables, dict(os.environ), crontab.exports_variables(job)
+            ),
+        )
+        print("\n".join(env_diff) if env_diff else "No environment differences.")
+    if args.banner:
+        banner: str = _make_banner(job)
+        if banner:
+            print(banner)
+    lock: Optional[TextIO] = None
+    if args.single_instance and fcntl is not None:
+        try:
+            lock = _acquire_lock(_make_lock_path(job, os.environ))
+        except BlockingIOError:
+            print(_color_error("This job is already running."))
+            return 1
+        except OSError as e:
+            print(_color_error(f"Cannot lock job: {e.strerror}."))
+            return 1
+    env: Optional[dict] = None
+    if args.export_termsize:
+        size: Optional[os.terminal_size] = _get_terminal_size()
+        if size is not None:
+            env = _make_termsize_env(size)
+        else:
+            print(
+                _color_error("Cannot detect terminal size, ignoring --export-termsize.")
+            )
+    print(_color_highlight("$"), job.job)
+    cwd: Path = Path.cwd() if args.cwd_current else Path().home()
+    try:
+        result: RunResult = crontab.run(
+            job,
+            wrapper,
+            args.source,
+            cwd,
+            args.shell_arg,
+            args.as_script,
+            env_from=env_from,
+            timeout=args.timeout,
+            env=env,
+        )
+    finally:
+        if lock is not None:
+            lock.close()
+    if args.audit_log is not None:
+        _append_audit_line(
+            args.audit_log,
+            _format_audit_line(
+                _get_user(), int(job_number), job, result, args.hash_algo
+            ),
+        )
+    if args.timeout is not None and result.timed_out:
+        print(_color_error(f"Job timed out after {args.timeout:g} seconds."))
+    exit_message: Optional[str] = _get_exit_message(args.on_exit, result.exit_code)
+    if exit_message is not None:
+        print(exit_message)
+    if args.stats and result.resource_usage is not None:
+        print(_color_attenuate(_format_resource_usage(result.resource_usage)))
+
+    return 0
+
+
+def main(argv: Optional[list] = None) -> int:
+    try:
+        return _main(argv)
+    except BrokenPipeError:
+        # Output is piped into a program that exited early (e.g., `head`).
+        # There is no one left to read, which is not an error. Make sure
+        # flushing stdout on exit does not raise again.
+        try:
+            devnull: int = os.open(os.devnull, os.O_WRONLY)
+            os.dup2(devnull, sys.stdout.fileno())
+        except (OSError, ValueError):  # Not backed by a file descriptor.
+            pass
+        return 0
+
+
+def _main(argv: Optional[list]) -> int:
+    args: argparse.Namespace = _parse_args(argv)
+    _set_theme(_resolve_theme(args.theme, os.environ))
+
+    if args.color_test:
+        print(_format_color_test())
+        return 0
+
+    try:
+        parser = CrontabParser(
+            args.umask_directives,
+            quartz_marks=args.quartz_marks,
+            trim_commands=not args.no_trim,
+        )
+        crontab: Crontab
+        if args.file is not None:
+            crontab = get_crontab_from_file(args.file, parser)
+            if str(args.file) == "-":
+                _reattach_stdin_to_terminal()
+        else:
+            crontab = get_crontab(parser, args.user)
     except CrontabReadError as e:
         print(_color_error(str(e)))
+        if e.signal is not None:
+            print(f"crontab was terminated by signal {e.signal}.")
         if e.detail:
             print(e.detail)
         return e.exit_code
 
+    if args.check:
+        problems, notes = _check_crontab(crontab)
+        for note in notes:
+            print(_color_attenuate(note))
+        for problem in problems:
+            print(_color_error(problem))
+        if problems:
+            return 1
+        print("No problems found.")
+        return 0
+
+    if args.show_variables:
+        if crontab.variables:
+            print(_format_variables(crontab.variables))
+        else:
+            print("No variables.")
+        return 0
+
+    # Checked once, so that `--interactive` does not loop on the error,
+    # and applied to machine-readable listings too.
+    try:
+        job_filter: Optional[Callable] = _make_args_filter(args)
+    except re.error as e:
+        print(_color_error(f"Invalid filter: {e}."))
+        return 1
+    listed: MenuOptions = MenuOptions(hide_reboot=args.no_reboot, job_filter=job_filter)
+
+    if args.tsv:
+        print(_format_tsv(crontab, args.hash_algo, listed.lists))
+        return 0
+
+    if args.as_yaml:
+        print(_format_yaml(crontab, args.hash_algo, listed.lists))
+        return 0
+
+    if args.snapshot is not None:
+        _warn_duplicate_fingerprints(crontab, args.hash_algo)
+        try:
+            fingerprints: list = crontab.fingerprint_snapshot(args.hash_algo)
+            _write_snapshot(args.snapshot, fingerprints)
+        except OSError as e:
+            print(_color_error(f"Cannot write snapshot: {e.strerror}."))
+            return 1
+        return 0
+
+    if args.only_changed is not None:
+        try:
+            snapshot: set = _read_snapshot(args.only_changed)
+        except OSError as e:
+            print(_color_error(f"Cannot read snapshot: {e.strerror}."))
+            return 1
+        except ValueError:
+            print(_color_error("Invalid snapshot."))
+            return 1
+        _warn_duplicate_fingerprints(crontab, args.hash_algo)
+        changed_jobs: list = _changed_jobs(crontab, snapshot, args.hash_algo)
+        for job_number, job in changed_jobs:
+            print(f"{_format_job_number(job_number)} {job.schedule} {job.job}")
+        if not changed_jobs:
+            print("No changes.")
+        return 0
+
     if not crontab:
         print("No jobs to run.")
         return 0
 
-    for i, job in enumerate(crontab.jobs):
-        job_number: str = _color_highlight(str(i + 1)) + "."
-        description: str = f"{job.description} " if job.description else ""
-        schedule: str = _color_attenuate(job.schedule)
-        command: str = _color_attenuate(job.job) if description else job.job
-        print(f"{job_number} {description}{schedule} {command}")
+    if args.locate is not None:
+        try:
+            job: CronJob = _get_job_from_number(crontab, args.locate)
+        except ValueError:
+            print(_color_error("Invalid job number."))
+            return 1
+        try:
+            source_line: str = crontab.source_line(job)
+        except ValueError:
+            print(_color_error("Cannot locate job in crontab source."))
+            return 1
+        print(f"{job.line_number}: {source_line}")
+        return 0
 
-    job_number: str = input(">>> Select a job to run: ")
-    if not job_number:
+    if args.hash is not None:
+        try:
+            job: CronJob = _get_job_from_number(crontab, args.hash)
+        except ValueError:
+            print(_color_error("Invalid job number."))
+            return 1
+        print(job.fingerprint_hex(args.hash_algo, args.hash_schedule))
         return 0
-    try:
-        job_number: int = int(job_number)
-        if not 0 < job_number <= len(crontab.jobs):
-            raise ValueError
-    except ValueError:
-        print(_color_error("Invalid job number."))
-        return 1
 
-    job: CronJob = crontab.jobs[job_number - 1]
-    print(_color_highlight("$"), job.job)
-    crontab.run(job)
+    if args.export_env is not None:
+        try:
+            job: CronJob = _get_job_from_number(crontab, args.export_env)
+        except ValueError:
+            print(_color_error("Invalid job number."))
+            return 1
+        exports: str = _format_exports(crontab.variables_in_scope(job))
+        if exports:
+            print(exports)
+        return 0
 
-    return 0
+    env_from: Optional[CronJob] = None
+    if args.env_from_job is not None:
+        try:
+            env_from = _get_job_from_number(crontab, args.env_from_job)
+        except ValueError:
+            print(_color_error("Invalid job number for --env-from-job."))
+            return 1
+
+    if not crontab.filter_jobs(listed.lists):
+        if job_filter is not None:
+            print(_color_error("No jobs match the filter."))
+            return 1
+        print("No jobs to run.")
+        return 0
+
+    while True:
+        exit_code: Optional[int] = _select_and_run(
+            crontab, args, env_from, job_filter
+        )
+        if exit_code is None:
+            return 0
+        if not args.interactive:
+            return exit_code
 
 
 if __name__ == "__main__":
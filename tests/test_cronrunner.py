@@ -1,19 +1,325 @@
+import argparse
+import io
+import json
+import os
+import random
+import re
+import signal
+import subprocess
+import tempfile
+import time
 import unittest
+from contextlib import redirect_stderr, redirect_stdout
+from datetime import datetime, timedelta, timezone
+from functools import partial
 from pathlib import Path
-from unittest.mock import Mock
+from typing import Optional, TextIO
+from unittest.mock import ANY, Mock, patch
 
 import cronrunner.cronrunner as cronrunner
 from cronrunner.cronrunner import (
+    Blank,
     Comment,
     CronJob,
     Crontab,
     CrontabParser,
+    CrontabReader,
+    CrontabReadError,
+    MenuOptions,
+    RawToken,
+    ResourceUsage,
+    RunResult,
+    ScheduleFields,
     Unknown,
     Variable,
 )
 
 CWD: dict = {"cwd": Path().home()}
 
+# Some tests replace `subprocess.run` and `subprocess.Popen` with mocks.
+# System tests need the real thing.
+SUBPROCESS_RUN = subprocess.run
+SUBPROCESS_POPEN = subprocess.Popen
+
+
+def wait_until_gone(pid: int, timeout: float = 2) -> bool:
+    """Wait for a process to exit. Zombies count as exited."""
+    deadline: float = time.monotonic() + timeout
+    while time.monotonic() < deadline:
+        try:
+            with open(f"/proc/{pid}/stat") as f:
+                if f.read().rsplit(")", 1)[1].split()[0] == "Z":
+                    return True
+        except FileNotFoundError:
+            return True
+        time.sleep(0.01)
+    return False
+
+
+class TestCrontabReader(unittest.TestCase):
+    def test_non_zero_exit(self) -> None:
+        cronrunner.subprocess.run = Mock(
+            side_effect=subprocess.CalledProcessError(2, ["crontab", "-l"])
+        )
+        with self.assertRaises(CrontabReadError) as ctx:
+            CrontabReader().read()
+        self.assertEqual(ctx.exception.exit_code, 2)
+        self.assertIsNone(ctx.exception.signal)
+
+    def test_killed_by_signal(self) -> None:
+        cronrunner.subprocess.run = Mock(
+            side_effect=subprocess.CalledProcessError(-11, ["crontab", "-l"])
+        )
+        with self.assertRaises(CrontabReadError) as ctx:
+            CrontabReader().read()
+        self.assertEqual(ctx.exception.exit_code, 1)
+        self.assertEqual(ctx.exception.signal, 11)
+
+    def test_executable_not_found(self) -> None:
+        cronrunner.subprocess.run = Mock(side_effect=FileNotFoundError)
+        with self.assertRaises(CrontabReadError) as ctx:
+            CrontabReader().read()
+        self.assertEqual(ctx.exception.exit_code, 1)
+        self.assertIsNone(ctx.exception.signal)
+
+    def test_normalize_crlf(self) -> None:
+        self.assertEqual(
+            CrontabReader.normalize("FOO=bar\r\n* * * * * echo $FOO\r\n"),
+            "FOO=bar\n* * * * * echo $FOO\n",
+        )
+
+    def test_normalize_strips_trailing_banner(self) -> None:
+        self.assertEqual(
+            CrontabReader.normalize(
+                "* * * * * echo foo\r\nno crontab for root\r\n\r\n"
+            ),
+            "* * * * * echo foo\n",
+        )
+
+    def test_normalize_keeps_regular_last_line(self) -> None:
+        self.assertEqual(
+            CrontabReader.normalize("# no crontab for root\n@daily :"),
+            "# no crontab for root\n@daily :\n",
+        )
+
+    def test_normalize_empty_crontab(self) -> None:
+        self.assertEqual(CrontabReader.normalize(""), "")
+
+    def test_read_normalized(self) -> None:
+        cronrunner.subprocess.run = Mock(
+            return_value=subprocess.CompletedProcess(
+                ["crontab", "-l"], 0, stdout="@daily :\r\n"
+            )
+        )
+        self.assertEqual(CrontabReader().read(), "@daily :\r\n")
+        self.assertEqual(CrontabReader().read_normalized(), "@daily :\n")
+
+    def test_read_current_user(self) -> None:
+        cronrunner.subprocess.run = Mock(
+            return_value=subprocess.CompletedProcess([], 0, stdout="")
+        )
+        CrontabReader().read()
+        self.assertListEqual(
+            cronrunner.subprocess.run.call_args.args[0], ["crontab", "-l"]
+        )
+
+    def test_read_for_user(self) -> None:
+        cronrunner.subprocess.run = Mock(
+            return_value=subprocess.CompletedProcess([], 0, stdout="@daily :\n")
+        )
+        self.assertEqual(CrontabReader.read_for_user("alice"), "@daily :\n")
+        self.assertListEqual(
+            cronrunner.subprocess.run.call_args.args[0],
+            ["crontab", "-u", "alice", "-l"],
+        )
+        CrontabReader("bob").read()
+        self.assertListEqual(
+            cronrunner.subprocess.run.call_args.args[0],
+            ["crontab", "-u", "bob", "-l"],
+        )
+
+    def test_read_for_user_error(self) -> None:
+        cronrunner.subprocess.run = Mock(
+            side_effect=subprocess.CalledProcessError(1, ["crontab"], stderr="denied")
+        )
+        with self.assertRaises(CrontabReadError) as ctx:
+            CrontabReader.read_for_user("alice")
+        self.assertEqual(str(ctx.exception), "Cannot read crontab of user alice.")
+        self.assertEqual(ctx.exception.detail, "denied")
+
+
+class TestHash(unittest.TestCase):
+    def test_djb2(self) -> None:
+        self.assertEqual(cronrunner.djb2(b""), 5381)
+        self.assertEqual(cronrunner.djb2(b"a"), 177670)
+        self.assertEqual(cronrunner.djb2(b"hello, world"), 0xD31C3A5DB0E4250D)
+
+    def test_djb2_wraps_at_64_bits(self) -> None:
+        self.assertLess(cronrunner.djb2(b"x" * 100), 2**64)
+
+    def test_fnv1a(self) -> None:
+        self.assertEqual(cronrunner.fnv1a(b""), 0xCBF29CE484222325)
+        self.assertEqual(cronrunner.fnv1a(b"a"), 0xAF63DC4C8601EC8C)
+        self.assertEqual(cronrunner.fnv1a(b"foobar"), 0x85944171F73967E8)
+
+
+class TestScheduleNames(unittest.TestCase):
+    def test_day_name(self) -> None:
+        self.assertEqual(
+            cronrunner.schedule_names_to_numbers("sun", "day_of_week"), "0"
+        )
+
+    def test_day_range(self) -> None:
+        self.assertEqual(
+            cronrunner.schedule_names_to_numbers("MON-FRI", "day_of_week"), "1-5"
+        )
+
+    def test_month_list(self) -> None:
+        self.assertEqual(
+            cronrunner.schedule_names_to_numbers("JAN,DEC", "month"), "1,12"
+        )
+
+    def test_numbers_are_left_untouched(self) -> None:
+        self.assertEqual(
+            cronrunner.schedule_names_to_numbers("*/2,5", "month"), "*/2,5"
+        )
+
+    def test_invalid_name(self) -> None:
+        with self.assertRaises(ValueError):
+            cronrunner.schedule_names_to_numbers("JAN", "day_of_week")
+        with self.assertRaises(ValueError):
+            cronrunner.schedule_names_to_numbers("monday", "day_of_week")
+        with self.assertRaises(ValueError):
+            cronrunner.schedule_names_to_numbers("mon", "hour")
+
+
+class TestParseScheduleField(unittest.TestCase):
+    def test_any(self) -> None:
+        self.assertSetEqual(
+            cronrunner.parse_schedule_field("*", "hour"), set(range(24))
+        )
+
+    def test_range_step_and_list(self) -> None:
+        self.assertSetEqual(
+            cronrunner.parse_schedule_field("*/15", "minute"), {0, 15, 30, 45}
+        )
+        self.assertSetEqual(
+            cronrunner.parse_schedule_field("3-6,9", "hour"), {3, 4, 5, 6, 9}
+        )
+        self.assertSetEqual(
+            cronrunner.parse_schedule_field("10/20", "minute"), {10, 30, 50}
+        )
+
+    def test_names(self) -> None:
+        self.assertSetEqual(
+            cronrunner.parse_schedule_field("jan,MAR", "month"), {1, 3}
+        )
+
+    def test_sunday_is_0_and_7(self) -> None:
+        self.assertSetEqual(
+            cronrunner.parse_schedule_field("5-7", "day_of_week"), {5, 6, 0}
+        )
+
+    def test_invalid(self) -> None:
+        for value, field_name in (
+            ("60", "minute"),
+            ("0", "day_of_month"),
+            ("6-3", "hour"),
+            ("*/0", "minute"),
+            ("1-", "hour"),
+            ("", "hour"),
+        ):
+            with self.subTest(value=value), self.assertRaises(ValueError):
+                cronrunner.parse_schedule_field(value, field_name)
+
+
+class TestNextRunAfter(unittest.TestCase):
+    NOW: datetime = datetime(2026, 10, 16, 12, 34, 56, tzinfo=timezone.utc)
+
+    def next_run(self, schedule: str, now: datetime = NOW) -> Optional[datetime]:
+        return CronJob(schedule, ":", "").next_run_after(now)
+
+    def test_every_minute(self) -> None:
+        self.assertEqual(
+            self.next_run("* * * * *"),
+            datetime(2026, 10, 16, 12, 35, tzinfo=timezone.utc),
+        )
+
+    def test_is_strictly_after_now(self) -> None:
+        now = datetime(2026, 10, 16, 12, 35, tzinfo=timezone.utc)
+        self.assertEqual(
+            self.next_run("35 12 * * *", now),
+            datetime(2026, 10, 17, 12, 35, tzinfo=timezone.utc),
+        )
+
+    def test_ranges_steps_and_lists(self) -> None:
+        self.assertEqual(
+            self.next_run("*/15 3-6,9-12 * * *"),
+            datetime(2026, 10, 16, 12, 45, tzinfo=timezone.utc),
+        )
+        self.assertEqual(
+            self.next_run("0 1,15 * * *"),
+            datetime(2026, 10, 16, 15, 0, tzinfo=timezone.utc),
+        )
+
+    def test_names(self) -> None:
+        self.assertEqual(
+            self.next_run("5 4 * * sun"),  # 2026-10-16 is a Friday.
+            datetime(2026, 10, 18, 4, 5, tzinfo=timezone.utc),
+        )
+        self.assertEqual(
+            self.next_run("0 0 1 jan *"),
+            datetime(2027, 1, 1, 0, 0, tzinfo=timezone.utc),
+        )
+
+    def test_day_of_month_or_day_of_week(self) -> None:
+        self.assertEqual(
+            self.next_run("0 0 1 * mon"),
+            datetime(2026, 10, 19, 0, 0, tzinfo=timezone.utc),
+        )
+
+    def test_shortcuts(self) -> None:
+        self.assertEqual(
+            self.next_run("@daily"), datetime(2026, 10, 17, tzinfo=timezone.utc)
+        )
+        self.assertEqual(
+            self.next_run("@weekly"), datetime(2026, 10, 18, tzinfo=timezone.utc)
+        )
+        self.assertEqual(
+            self.next_run("@yearly"), datetime(2027, 1, 1, tzinfo=timezone.utc)
+        )
+
+    def test_leap_years(self) -> None:
+        self.assertEqual(
+            self.next_run("0 0 29 2 *"), datetime(2028, 2, 29, tzinfo=timezone.utc)
+        )
+        self.assertEqual(
+            self.next_run("0 0 29 2 *", datetime(2096, 3, 1)),
+            datetime(2104, 2, 29),
+        )
+
+    def test_is_utc_without_dst(self) -> None:
+        # Europe switches to summer time at 01:00 UTC on 2026-03-29.
+        now = datetime(2026, 3, 29, 0, 30, tzinfo=timezone.utc)
+        self.assertEqual(
+            self.next_run("0 * * * *", now),
+            datetime(2026, 3, 29, 1, 0, tzinfo=timezone.utc),
+        )
+
+    def test_quartz_marks(self) -> None:
+        job: CronJob = CrontabParser(quartz_marks=True).parse_line("0 0 * * ? :")
+        self.assertEqual(
+            job.next_run_after(self.NOW), datetime(2026, 10, 17, tzinfo=timezone.utc)
+        )
+        job = CrontabParser().parse_line("0 0 * * ? :")
+        self.assertIsNone(job.next_run_after(self.NOW))
+
+    def test_no_next_run(self) -> None:
+        self.assertIsNone(self.next_run("@reboot"))
+        self.assertIsNone(self.next_run("@fortnightly"))
+        self.assertIsNone(self.next_run("0 0 30 2 *"))
+        self.assertIsNone(self.next_run("61 * * * *"))
+
 
 class TestCrontabParser(unittest.TestCase):
     def test_regular_crontab(self) -> None:
@@ -100,141 +406,2722 @@ class TestCrontabParser(unittest.TestCase):
             ],
         )
 
-    def test_whitespace_is_cleared_around_variables(self) -> None:
+    def test_shebang_is_unknown(self) -> None:
         parser = CrontabParser()
-        nodes: list = parser.parse("   FOO     =   bar   ")
+        nodes: list = parser.parse(
+            "#!/bin/bash\n### Section\n## Description.\n@daily :\n"
+        )
         self.assertListEqual(
             nodes,
             [
-                Variable(identifier="FOO", value="bar"),
+                Unknown(
+                    value="#!/bin/bash", hint="shebang lines are not valid in crontabs"
+                ),
+                Comment(value="### Section"),
+                Comment(value="## Description."),
+                CronJob(schedule="@daily", job=":", description="Description."),
             ],
         )
 
+    def test_umask_directive_is_a_comment_by_default(self) -> None:
+        parser = CrontabParser()
+        nodes: list = parser.parse("#@umask 077\n@daily :")
+        self.assertListEqual(
+            nodes,
+            [
+                Comment(value="#@umask 077"),
+                CronJob(schedule="@daily", job=":", description=""),
+            ],
+        )
 
-class TestCrontab(unittest.TestCase):
-    @classmethod
-    def setUpClass(cls) -> None:
-        cls.nodes: list = [
-            Comment(value="# CronRunner Demo"),
-            Comment(value="# ---------------"),
-            CronJob(
-                schedule="@reboot",
-                job="/usr/bin/bash ~/startup.sh",
-                description="",
-            ),
-            Comment(
-                value="# Double-hash comments (##) immediately preceding a job are used as"
-            ),
-            Comment(value="# description. See below:"),
-            Comment(value="## Update brew."),
-            CronJob(
-                schedule="30 20 * * *",
-                job="/usr/local/bin/brew update && /usr/local/bin/brew upgrade",
-                description="Update brew.",
-            ),
-            Variable(identifier="FOO", value="bar"),
-            Comment(value="## Print variable."),
-            CronJob(
-                schedule="* * * * *",
-                job="echo $FOO",
-                description="Print variable.",
-            ),
-            Comment(value="# Do nothing (this is a regular comment)."),
-            CronJob(
-                schedule="@reboot",
-                job=":",
-                description="",
-            ),
-            Variable(identifier="SHELL", value="/bin/bash"),
-            CronJob(
-                schedule="@hourly",
-                job="echo 'I am echoed by bash!'",
-                description="",
-            ),
-        ]
-
-    def setUp(self) -> None:
-        cronrunner.subprocess.run = Mock()
-
-    def test_default_shell(self) -> None:
-        self.assertEqual(Crontab.DEFAULT_SHELL, "/bin/sh")
+    def test_umask_directive_applies_to_following_jobs(self) -> None:
+        parser = CrontabParser(umask_directives=True)
+        nodes: list = parser.parse(
+            "@reboot :\n#@umask 077\n@daily :\n@hourly :\n#@umask 0022\n@weekly :"
+        )
+        self.assertListEqual(
+            nodes,
+            [
+                CronJob(schedule="@reboot", job=":", description=""),
+                Comment(value="#@umask 077"),
+                CronJob(schedule="@daily", job=":", description="", umask=0o077),
+                CronJob(schedule="@hourly", job=":", description="", umask=0o077),
+                Comment(value="#@umask 0022"),
+                CronJob(schedule="@weekly", job=":", description="", umask=0o022),
+            ],
+        )
 
-    def test_bool_true(self) -> None:
-        crontab = Crontab(self.nodes)
-        self.assertTrue(bool(crontab))
+    def test_umask_directive_does_not_leak_between_parses(self) -> None:
+        parser = CrontabParser(umask_directives=True)
+        parser.parse("#@umask 077")
+        nodes: list = parser.parse("@daily :")
+        self.assertIsNone(nodes[0].umask)
 
-    def test_bool_false(self) -> None:
-        crontab = Crontab([])
-        self.assertFalse(bool(crontab))
+    def test_invalid_umask_directive(self) -> None:
+        parser = CrontabParser(umask_directives=True)
+        nodes: list = parser.parse("#@umask 999\n#@umask")
+        self.assertListEqual(
+            nodes,
+            [
+                Unknown(
+                    value="#@umask 999", hint="umask must be an octal mode, e.g., 022"
+                ),
+                Unknown(value="#@umask", hint="umask must be an octal mode, e.g., 022"),
+            ],
+        )
 
-    def test_list_of_jobs(self) -> None:
-        crontab = Crontab(self.nodes)
+    def test_extra_comment_prefixes_are_unknown_by_default(self) -> None:
+        nodes: list = CrontabParser().parse("; comment\nREM comment")
         self.assertListEqual(
-            crontab.jobs, [node for node in self.nodes if isinstance(node, CronJob)]
+            nodes, [Unknown(value="; comment"), Unknown(value="REM comment")]
         )
 
-    def test_working_directory_is_home_directory(self) -> None:
-        crontab = Crontab(self.nodes)
-        crontab.run(crontab.jobs[0])
-        self.assertEqual(
-            cronrunner.subprocess.run.call_args.kwargs["cwd"],
-            Path().home(),
+    def test_extra_comment_prefixes(self) -> None:
+        parser = CrontabParser(extra_comment_prefixes=(";", "REM"))
+        nodes: list = parser.parse(
+            "; comment\n;comment\nREM comment\nREM\nREM FOO=bar\n# comment"
+        )
+        self.assertListEqual(
+            nodes,
+            [
+                Comment(value="; comment"),
+                Comment(value=";comment"),
+                Comment(value="REM comment"),
+                Comment(value="REM"),
+                Comment(value="REM FOO=bar"),
+                Comment(value="# comment"),
+            ],
         )
 
-    def test_run_cron_without_variable(self) -> None:
-        crontab = Crontab(self.nodes)
-        crontab.run(crontab.jobs[0])
-        cronrunner.subprocess.run.assert_called_with(
-            [Crontab.DEFAULT_SHELL, "-c", "/usr/bin/bash ~/startup.sh"], **CWD
+    def test_extra_word_comment_prefix_needs_boundary(self) -> None:
+        parser = CrontabParser(extra_comment_prefixes=("REM",))
+        nodes: list = parser.parse("REMOTE=host\nREMARK")
+        self.assertListEqual(
+            nodes,
+            [Variable(identifier="REMOTE", value="host"), Unknown(value="REMARK")],
         )
 
-    def test_run_cron_with_variable(self) -> None:
-        crontab = Crontab(self.nodes)
-        crontab.run(crontab.jobs[2])
-        cronrunner.subprocess.run.assert_called_with(
-            [Crontab.DEFAULT_SHELL, "-c", "FOO=bar;echo $FOO"], **CWD
+    def test_schedule_whitespace_is_normalized(self) -> None:
+        parser = CrontabParser()
+        nodes: list = parser.parse(
+            "*\t*  * *\t\t*   echo  'a\tb'\n@daily\t  :\n* * *"
+        )
+        self.assertListEqual(
+            nodes,
+            [
+                CronJob(schedule="* * * * *", job="echo  'a\tb'", description=""),
+                CronJob(schedule="@daily", job=":", description=""),
+                Unknown(value="* * *", hint="job has no command"),
+            ],
         )
 
-    def test_run_cron_after_variable_but_not_stuck_to_it(self) -> None:
-        crontab = Crontab(self.nodes)
-        crontab.run(crontab.jobs[3])
-        cronrunner.subprocess.run.assert_called_with(
-            [Crontab.DEFAULT_SHELL, "-c", "FOO=bar;:"], **CWD
+    def test_job_without_command_is_unknown(self) -> None:
+        parser = CrontabParser()
+        nodes: list = parser.parse("@hourly\n@hourly   \n  0 * * * *  \n@daily :")
+        self.assertListEqual(
+            nodes,
+            [
+                Unknown(value="@hourly", hint="job has no command"),
+                Unknown(value="@hourly", hint="job has no command"),
+                Unknown(value="0 * * * *", hint="job has no command"),
+                CronJob(schedule="@daily", job=":", description=""),
+            ],
         )
 
-    def test_run_cron_with_default_shell(self) -> None:
-        crontab = Crontab(self.nodes)
-        crontab.run(crontab.jobs[0])
-        self.assertEqual(
-            cronrunner.subprocess.run.call_args.args[0][0], Crontab.DEFAULT_SHELL
+    def test_whitespace_is_cleared_around_variables(self) -> None:
+        parser = CrontabParser()
+        nodes: list = parser.parse("   FOO     =   bar   ")
+        self.assertListEqual(
+            nodes,
+            [
+                Variable(identifier="FOO", value="bar"),
+            ],
         )
 
-    def test_run_cron_with_different_shell(self) -> None:
-        crontab = Crontab(self.nodes)
-        crontab.run(crontab.jobs[4])
-        self.assertEqual(cronrunner.subprocess.run.call_args.args[0][0], "/bin/bash")
-        cronrunner.subprocess.run.assert_called_with(
-            ["/bin/bash", "-c", "FOO=bar;SHELL=/bin/bash;echo 'I am echoed by bash!'"],
-            **CWD,
+    def test_job_line_numbers_count_every_physical_line(self) -> None:
+        parser = CrontabParser()
+        nodes: list = parser.parse(
+            "# Comment.\n"
+            "\n"
+            "@reboot /usr/bin/bash ~/startup.sh\n"
+            "FOO=bar\n"
+            "\n"
+            "\n"
+            "## Print variable.\n"
+            "* * * * * echo $FOO\n"
         )
+        jobs: list = [node for node in nodes if isinstance(node, CronJob)]
+        self.assertEqual(jobs[0].line_number, 3)
+        self.assertEqual(jobs[1].line_number, 8)
 
-    def test_shell_is_reset_between_two_executions(self) -> None:
-        crontab = Crontab(self.nodes)
+    def test_every_token_has_a_line_number(self) -> None:
+        parser = CrontabParser(umask_directives=True, keep_blank_lines=True)
+        nodes: list = parser.parse(
+            "# Comment.\n"
+            "\n"
+            "FOO=bar\n"
+            "\n"
+            "\n"
+            "#!/bin/sh\n"
+            "#@umask 999\n"
+            "unknown :\n"
+            "@daily :\n"
+        )
+        self.assertListEqual(
+            [(type(node), node.line_number) for node in nodes],
+            [
+                (Comment, 1),
+                (Blank, 2),
+                (Variable, 3),
+                (Blank, 4),
+                (Blank, 5),
+                (Unknown, 6),
+                (Unknown, 7),
+                (Unknown, 8),
+                (CronJob, 9),
+            ],
+        )
 
-        crontab.run(crontab.jobs[4])
-        self.assertEqual(cronrunner.subprocess.run.call_count, 1)
-        self.assertEqual(cronrunner.subprocess.run.call_args.args[0][0], "/bin/bash")
+    def test_line_numbers_do_not_affect_equality(self) -> None:
+        self.assertEqual(Comment("# Comment.", 1), Comment("# Comment.", 2))
+        self.assertEqual(Variable("FOO", "bar", 1), Variable("FOO", "bar", 2))
+        self.assertEqual(Unknown("?", line_number=1), Unknown("?", line_number=2))
 
-        crontab.run(crontab.jobs[0])
-        self.assertEqual(cronrunner.subprocess.run.call_count, 2)
+    def test_parse_line(self) -> None:
+        parser = CrontabParser()
+        self.assertEqual(
+            parser.parse_line("  @daily  echo foo  "),
+            CronJob(schedule="@daily", job="echo foo", description=""),
+        )
+        self.assertEqual(parser.parse_line("@daily :").line_number, 1)
+        self.assertEqual(
+            parser.parse_line("FOO=bar"), Variable(identifier="FOO", value="bar")
+        )
+        self.assertEqual(parser.parse_line("# Comment."), Comment(value="# Comment."))
+        self.assertEqual(parser.parse_line("unknown :"), Unknown(value="unknown :"))
+        self.assertIsNone(parser.parse_line("  "))
+
+    def test_parse_line_matches_parse(self) -> None:
+        parser = CrontabParser()
+        for line in ("@daily :", "* * * * * echo $FOO", "FOO=bar", "#!/bin/sh", "?"):
+            with self.subTest(line=line):
+                self.assertListEqual(parser.parse(line), [parser.parse_line(line)])
+
+    def test_parse_line_has_fresh_state(self) -> None:
+        parser = CrontabParser(umask_directives=True)
+        parser.parse_line("#@umask 077")
+        self.assertIsNone(parser.parse_line("@daily :").umask)
+
+    def test_every_schedule(self) -> None:
+        self.assertListEqual(
+            CrontabParser().parse(
+                "@every 5m backup.sh\n@every\t1h30m  echo hi\n@every 5m\n@everyday :"
+            ),
+            [
+                CronJob("@every 5m", "backup.sh", ""),
+                CronJob("@every 1h30m", "echo hi", ""),
+                Unknown("@every 5m", "job has no command", 3),
+                CronJob("@everyday", ":", ""),
+            ],
+        )
+
+    def test_no_log_flag(self) -> None:
+        nodes: list = CrontabParser().parse(
+            "-@daily backup.sh\n-*/5 * * * * sync.sh\n@hourly :\n- @daily :"
+        )
+        self.assertListEqual(
+            nodes,
+            [
+                CronJob("@daily", "backup.sh", "", no_log=True),
+                CronJob("*/5 * * * *", "sync.sh", "", no_log=True),
+                CronJob("@hourly", ":", ""),
+                Unknown("- @daily :", line_number=4),
+            ],
+        )
+        self.assertFalse(nodes[2].no_log)
+
+    def test_no_log_flag_without_trimming(self) -> None:
+        self.assertEqual(
+            CrontabParser(trim_commands=False).parse_line("-@daily  backup.sh "),
+            CronJob("@daily", " backup.sh ", "", no_log=True),
+        )
+
+    def test_commands_are_trimmed_by_default(self) -> None:
+        self.assertListEqual(
+            CrontabParser().parse("* *  * * *\t  echo foo  \n@daily   :\t"),
+            [CronJob("* * * * *", "echo foo", ""), CronJob("@daily", ":", "")],
+        )
+
+    def test_commands_are_verbatim_without_trimming(self) -> None:
+        self.assertListEqual(
+            CrontabParser(trim_commands=False).parse(
+                "* *  * * *\t  echo foo  \n@daily   :\t"
+            ),
+            [CronJob("* * * * *", "  echo foo  ", ""), CronJob("@daily", "  :\t", "")],
+        )
+
+    def test_whitespace_only_command_without_trimming(self) -> None:
+        self.assertEqual(
+            CrontabParser(trim_commands=False).parse_line("@daily   "),
+            Unknown("@daily", "job has no command"),
+        )
+
+    def test_line_continuation(self) -> None:
+        nodes: list = CrontabParser().parse(
+            "# Comment.\n@daily echo foo \\\n  bar \\\nbaz\n@hourly :\n"
+        )
+        self.assertListEqual(
+            nodes,
+            [
+                Comment(value="# Comment."),
+                CronJob(schedule="@daily", job="echo foo   bar baz", description=""),
+                CronJob(schedule="@hourly", job=":", description=""),
+            ],
+        )
+        self.assertListEqual([node.line_number for node in nodes[1:]], [2, 5])
+
+    def test_escaped_backslash_does_not_continue_line(self) -> None:
+        nodes: list = CrontabParser().parse("@daily echo \\\\\n@hourly :")
+        self.assertListEqual(
+            nodes,
+            [
+                CronJob(schedule="@daily", job="echo \\\\", description=""),
+                CronJob(schedule="@hourly", job=":", description=""),
+            ],
+        )
+
+    def test_comment_does_not_continue_line(self) -> None:
+        nodes: list = CrontabParser().parse("# Comment \\\n@hourly :")
+        self.assertListEqual(
+            nodes,
+            [
+                Comment(value="# Comment \\"),
+                CronJob(schedule="@hourly", job=":", description=""),
+            ],
+        )
+
+    def test_backslash_on_last_line_is_kept(self) -> None:
+        nodes: list = CrontabParser().parse("@daily echo foo \\")
+        self.assertListEqual(
+            nodes, [CronJob(schedule="@daily", job="echo foo \\", description="")]
+        )
+
+    def test_lossless_parsing_keeps_continued_lines(self) -> None:
+        raw_tokens: list = CrontabParser().parse_lossless("@daily echo \\\n  foo\n")
+        self.assertListEqual(
+            raw_tokens,
+            [
+                RawToken(
+                    CronJob(schedule="@daily", job="echo   foo", description=""),
+                    "@daily echo \\\n  foo",
+                )
+            ],
+        )
+
+    def test_blank_lines_are_skipped_by_default(self) -> None:
+        nodes: list = CrontabParser().parse("# Comment.\n\n  \n@daily :\n")
+        self.assertNotIn(Blank(), nodes)
+
+    def test_keep_blank_lines(self) -> None:
+        parser = CrontabParser(keep_blank_lines=True)
+        nodes: list = parser.parse("# Comment.\n\n  \n## Description.\n\n@daily :")
+        self.assertListEqual(
+            nodes,
+            [
+                Comment(value="# Comment."),
+                Blank(),
+                Blank(),
+                Comment(value="## Description."),
+                Blank(),
+                CronJob(schedule="@daily", job=":", description="Description."),
+            ],
+        )
+
+    def test_lossless_reconstruction_with_blank_lines(self) -> None:
+        crontab: str = "# Comment.\n\nFOO=bar\n\t\n\n@daily echo $FOO\n"
+        raw_tokens: list = CrontabParser(keep_blank_lines=True).parse_lossless(
+            crontab
+        )
+        self.assertEqual(
+            "".join(f"{raw_token.raw}\n" for raw_token in raw_tokens), crontab
+        )
+
+    def test_lossless_parsing_keeps_raw_lines(self) -> None:
+        parser = CrontabParser()
+        raw_tokens: list = parser.parse_lossless(
+            "# Comment.  \n"
+            "\n"
+            "  FOO   =  bar\n"
+            "## Print variable.\n"
+            "*  *   * * *  echo  $FOO  \n"
+            "\tunknown :\n"
+        )
+        self.assertListEqual(
+            raw_tokens,
+            [
+                RawToken(Comment(value="# Comment."), "# Comment.  "),
+                RawToken(Variable(identifier="FOO", value="bar"), "  FOO   =  bar"),
+                RawToken(Comment(value="## Print variable."), "## Print variable."),
+                RawToken(
+                    CronJob(
+                        schedule="* * * * *",
+                        job="echo  $FOO",
+                        description="Print variable.",
+                    ),
+                    "*  *   * * *  echo  $FOO  ",
+                ),
+                RawToken(Unknown(value="unknown :"), "\tunknown :"),
+            ],
+        )
+
+
+class TestCrontab(unittest.TestCase):
+    @classmethod
+    def setUpClass(cls) -> None:
+        cls.nodes: list = [
+            Comment(value="# CronRunner Demo"),
+            Comment(value="# ---------------"),
+            CronJob(
+                schedule="@reboot",
+                job="/usr/bin/bash ~/startup.sh",
+                description="",
+            ),
+            Comment(
+                value="# Double-hash comments (##) immediately preceding a job are used as"
+            ),
+            Comment(value="# description. See below:"),
+            Comment(value="## Update brew."),
+            CronJob(
+                schedule="30 20 * * *",
+                job="/usr/local/bin/brew update && /usr/local/bin/brew upgrade",
+                description="Update brew.",
+            ),
+            Variable(identifier="FOO", value="bar"),
+            Comment(value="## Print variable."),
+            CronJob(
+                schedule="* * * * *",
+                job="echo $FOO",
+                description="Print variable.",
+            ),
+            Comment(value="# Do nothing (this is a regular comment)."),
+            CronJob(
+                schedule="@reboot",
+                job=":",
+                description="",
+            ),
+            Variable(identifier="SHELL", value="/bin/bash"),
+            CronJob(
+                schedule="@hourly",
+                job="echo 'I am echoed by bash!'",
+                description="",
+            ),
+        ]
+
+    def setUp(self) -> None:
+        cronrunner.subprocess.run = Mock()
+        patcher = patch.object(cronrunner.subprocess, "Popen")
+        patcher.start().return_value.wait.return_value = 0
+        self.addCleanup(patcher.stop)
+
+    def test_default_shell(self) -> None:
+        self.assertEqual(Crontab.DEFAULT_SHELL, "/bin/sh")
+
+    def test_bool_true(self) -> None:
+        crontab = Crontab(self.nodes)
+        self.assertTrue(bool(crontab))
+
+    def test_bool_false(self) -> None:
+        crontab = Crontab([])
+        self.assertFalse(bool(crontab))
+
+    def test_list_of_jobs(self) -> None:
+        crontab = Crontab(self.nodes)
+        self.assertListEqual(
+            crontab.jobs, [node for node in self.nodes if isinstance(node, CronJob)]
+        )
+
+    def test_jobs_are_in_source_order(self) -> None:
+        fillers: list = [
+            Comment(value="# comment"),
+            Variable(identifier="FOO", value="bar"),
+            Unknown(value="unknown"),
+        ]
+        for seed in range(20):
+            rng = random.Random(seed)
+            nodes: list = []
+            expected: list = []
+            for i in range(rng.randint(1, 10)):
+                nodes += rng.choices(fillers, k=rng.randint(0, 5))
+                job = CronJob(schedule="@daily", job=f"job {i}", description="")
+                nodes.append(job)
+                expected.append(job)
+            nodes += rng.choices(fillers, k=rng.randint(0, 5))
+            crontab = Crontab(nodes)
+
+            self.assertListEqual(crontab.jobs, expected, f"seed={seed}")
+            for number, job in enumerate(expected, start=1):
+                self.assertIs(cronrunner._get_job_from_number(crontab, number), job)
+
+    def test_content_fingerprint_is_independent_of_position(self) -> None:
+        crontab = Crontab(
+            [
+                CronJob("@daily", "echo 'hello'", ""),
+                CronJob("@hourly", "backup", ""),
+                CronJob("0 0 * * *", "echo 'hello'", "Say hello."),
+            ]
+        )
+        first: CronJob = crontab.jobs[0]
+        third: CronJob = crontab.jobs[2]
+        self.assertEqual(first.content_fingerprint(), third.content_fingerprint())
+        self.assertEqual(
+            first.content_fingerprint("fnv"), third.content_fingerprint("fnv")
+        )
+        self.assertNotEqual(
+            first.content_fingerprint(), crontab.jobs[1].content_fingerprint()
+        )
+
+    def test_content_fingerprint_with_schedule(self) -> None:
+        first = CronJob("@daily", "echo 'hello'", "")
+        second = CronJob("@hourly", "echo 'hello'", "")
+        self.assertNotEqual(
+            first.content_fingerprint(include_schedule=True),
+            second.content_fingerprint(include_schedule=True),
+        )
+        self.assertEqual(
+            first.content_fingerprint(include_schedule=True),
+            cronrunner.djb2(b"@daily echo 'hello'"),
+        )
+
+    def test_format_fingerprint(self) -> None:
+        self.assertEqual(cronrunner.format_fingerprint(0x2B606), "2b606")
+        self.assertEqual(cronrunner.format_fingerprint(0xA), "a")
+
+    def test_fingerprint_hex(self) -> None:
+        self.assertEqual(
+            CronJob("@daily", "hello, world", "").fingerprint_hex(), "d31c3a5db0e4250d"
+        )
+        self.assertEqual(CronJob("@daily", "a", "").fingerprint_hex(), "2b606")
+        job = CronJob("@daily", "echo 'hello'", "")
+        self.assertEqual(
+            job.fingerprint_hex("fnv", include_schedule=True),
+            f"{job.content_fingerprint('fnv', include_schedule=True):x}",
+        )
+
+    def test_content_fingerprint_algorithms_differ(self) -> None:
+        job = CronJob("@daily", "echo 'hello'", "")
+        self.assertEqual(job.content_fingerprint(), cronrunner.djb2(b"echo 'hello'"))
+        self.assertEqual(
+            job.content_fingerprint("fnv"), cronrunner.fnv1a(b"echo 'hello'")
+        )
+
+    def test_list_of_variables(self) -> None:
+        crontab = Crontab(self.nodes)
+        self.assertListEqual(
+            crontab.variables,
+            [
+                Variable(identifier="FOO", value="bar"),
+                Variable(identifier="SHELL", value="/bin/bash"),
+            ],
+        )
+
+    def test_special_variables(self) -> None:
+        for identifier in ("SHELL", "HOME", "PATH", "MAILTO", "CRON_TZ"):
+            self.assertTrue(Variable(identifier=identifier, value="").is_special)
+        self.assertFalse(Variable(identifier="FOO", value="").is_special)
+        self.assertFalse(Variable(identifier="shell", value="").is_special)
+
+    def test_scheduled_jobs_exclude_reboot_jobs(self) -> None:
+        crontab = Crontab(self.nodes)
+        self.assertListEqual(
+            crontab.scheduled_jobs,
+            [crontab.jobs[1], crontab.jobs[2], crontab.jobs[4]],
+        )
+
+    def test_job_runs_at_reboot(self) -> None:
+        self.assertTrue(CronJob("@reboot", ":", "").runs_at_reboot)
+        self.assertFalse(CronJob("@daily", ":", "").runs_at_reboot)
+        self.assertFalse(CronJob("* * * * *", "echo @reboot", "").runs_at_reboot)
+
+    def test_schedule_fields(self) -> None:
+        job = CronJob("*/15 3-6,9-12 * * sun", ":", "")
+        self.assertEqual(
+            job.schedule_fields,
+            ScheduleFields(
+                minute="*/15",
+                hour="3-6,9-12",
+                day_of_month="*",
+                month="*",
+                day_of_week="sun",
+            ),
+        )
+        self.assertEqual(job.schedule, "*/15 3-6,9-12 * * sun")
+
+    def test_schedule_fields_with_quartz_marks(self) -> None:
+        job: CronJob = CrontabParser(quartz_marks=True).parse_line("0 0 ? * ? :")
+        self.assertEqual(job.schedule, "0 0 ? * ?")
+        self.assertEqual(job.schedule_fields, ScheduleFields("0", "0", "*", "*", "*"))
+
+    def test_schedule_fields_without_quartz_marks(self) -> None:
+        job: CronJob = CrontabParser().parse_line("0 0 * * ? :")
+        self.assertEqual(job.schedule_fields.day_of_week, "?")
+
+    def test_schedule_fields_of_shortcut(self) -> None:
+        self.assertIsNone(CronJob("@daily", ":", "").schedule_fields)
+        self.assertIsNone(CronJob("@reboot", ":", "").schedule_fields)
+
+    def test_schedule_fields_of_incomplete_schedule(self) -> None:
+        self.assertIsNone(CronJob("* * *", "", "").schedule_fields)
+
+    def test_render_menu(self) -> None:
+        crontab = Crontab(self.nodes)
+        menu = io.StringIO()
+        crontab.render_menu(MenuOptions(), menu)
+        self.assertEqual(
+            menu.getvalue(),
+            "\x1b[0;92m1\x1b[0m. \x1b[0;90m@reboot\x1b[0m "
+            "/usr/bin/bash ~/startup.sh\n"
+            "\x1b[0;92m2\x1b[0m. Update brew. \x1b[0;90m30 20 * * *\x1b[0m "
+            "\x1b[0;90m/usr/local/bin/brew update && /usr/local/bin/brew upgrade"
+            "\x1b[0m\n"
+            "\x1b[0;92m3\x1b[0m. Print variable. \x1b[0;90m* * * * *\x1b[0m "
+            "\x1b[0;90mecho $FOO\x1b[0m\n"
+            "\x1b[0;92m4\x1b[0m. \x1b[0;90m@reboot\x1b[0m :\n"
+            "\x1b[0;92m5\x1b[0m. \x1b[0;90m@hourly\x1b[0m "
+            "echo 'I am echoed by bash!'\n",
+        )
+
+    def test_render_menu_with_options(self) -> None:
+        crontab = Crontab(
+            [
+                CronJob("@reboot", ":", ""),
+                CronJob("* * * * *", "echo   foo", "Print foo."),
+            ]
+        )
+        menu = io.StringIO()
+        crontab.render_menu(
+            MenuOptions(
+                trim_command=True,
+                hide_reboot=True,
+                id_width=2,
+                icons=True,
+                unicode_icons=False,
+            ),
+            menu,
+        )
+        self.assertEqual(
+            menu.getvalue(),
+            " \x1b[0;92m2\x1b[0m. D  Print foo. \x1b[0;90m* * * * *\x1b[0m "
+            "\x1b[0;90mecho foo\x1b[0m\n",
+        )
+
+    def test_render_menu_with_next_run(self) -> None:
+        crontab = Crontab(
+            [CronJob("@reboot", ":", ""), CronJob("30 4 * * *", "backup.sh", "")]
+        )
+        menu = io.StringIO()
+        crontab.render_menu(
+            MenuOptions(next_run_from=datetime(2026, 10, 16, 12, 0)), menu
+        )
+        self.assertEqual(
+            menu.getvalue(),
+            "\x1b[0;92m1\x1b[0m. \x1b[0;90m@reboot\x1b[0m :\n"
+            "\x1b[0;92m2\x1b[0m. \x1b[0;90m30 4 * * *\x1b[0m backup.sh "
+            "\x1b[0;90m(next run: 2026-10-17 04:30)\x1b[0m\n",
+        )
+
+    def test_render_menu_with_filter(self) -> None:
+        crontab = Crontab(
+            [CronJob("@reboot", ":", ""), CronJob("@daily", "backup.sh", "")]
+        )
+        menu = io.StringIO()
+        crontab.render_menu(
+            MenuOptions(job_filter=lambda job: "backup" in job.job), menu
+        )
+        self.assertEqual(
+            menu.getvalue(), "\x1b[0;92m2\x1b[0m. \x1b[0;90m@daily\x1b[0m backup.sh\n"
+        )
+
+    def test_hidden_by(self) -> None:
+        reboot = CronJob("@reboot", "backup.sh", "")
+        daily = CronJob("@daily", ":", "")
+        options = MenuOptions(
+            hide_reboot=True, job_filter=lambda job: "backup" in job.job
+        )
+        self.assertEqual(options.hidden_by(reboot), "--no-reboot")
+        self.assertEqual(options.hidden_by(daily), "the filter")
+        self.assertIsNone(MenuOptions().hidden_by(reboot))
+        self.assertFalse(options.lists(daily))
+        self.assertTrue(MenuOptions().lists(daily))
+
+    def test_tokens_to_json(self) -> None:
+        crontab = Crontab(
+            CrontabParser(keep_blank_lines=True).parse(
+                '# Comment.\n\nFOO="bar"\n## Say "hi".\n@daily echo "hi"\n?\n'
+            )
+        )
+        self.assertListEqual(
+            json.loads(crontab.tokens_to_json()),
+            [
+                {"type": "comment", "value": "# Comment.", "line_number": 1},
+                {"type": "blank", "line_number": 2},
+                {
+                    "type": "variable",
+                    "identifier": "FOO",
+                    "value": '"bar"',
+                    "line_number": 3,
+                },
+                {"type": "comment", "value": '## Say "hi".', "line_number": 4},
+                {
+                    "type": "job",
+                    "schedule": "@daily",
+                    "job": 'echo "hi"',
+                    "description": 'Say "hi".',
+                    "line_number": 5,
+                    "umask": None,
+                    "quartz_marks": False,
+                    "no_log": False,
+                },
+                {"type": "unknown", "value": "?", "hint": "", "line_number": 6},
+            ],
+        )
+
+    def test_filter_jobs(self) -> None:
+        crontab = Crontab(self.nodes)
+        self.assertListEqual(
+            crontab.filter_jobs(lambda job: job.runs_at_reboot),
+            [crontab.jobs[0], crontab.jobs[3]],
+        )
+
+    def test_working_directory_is_home_directory(self) -> None:
+        crontab = Crontab(self.nodes)
+        crontab.run(crontab.jobs[0])
+        self.assertEqual(
+            cronrunner.subprocess.Popen.call_args.kwargs["cwd"],
+            Path().home(),
+        )
+
+    def test_extra_environment(self) -> None:
+        crontab = Crontab(self.nodes)
+        crontab.run(crontab.jobs[0], env={"COLUMNS": "100"})
+        env: dict = cronrunner.subprocess.Popen.call_args.kwargs["env"]
+        self.assertEqual(env["COLUMNS"], "100")
+        self.assertEqual(env["PATH"], os.environ["PATH"])
+
+    def test_no_extra_environment_inherits_environment(self) -> None:
+        crontab = Crontab(self.nodes)
+        crontab.run(crontab.jobs[0])
+        self.assertNotIn("env", cronrunner.subprocess.Popen.call_args.kwargs)
+
+    def test_working_directory_can_be_changed(self) -> None:
+        crontab = Crontab(self.nodes)
+        crontab.run(crontab.jobs[0], cwd=Path("/tmp"))
+        self.assertEqual(
+            cronrunner.subprocess.Popen.call_args.kwargs["cwd"],
+            Path("/tmp"),
+        )
+
+    def test_run_cron_without_variable(self) -> None:
+        crontab = Crontab(self.nodes)
+        crontab.run(crontab.jobs[0])
+        cronrunner.subprocess.Popen.assert_called_with(
+            [Crontab.DEFAULT_SHELL, "-c", "/usr/bin/bash ~/startup.sh"], **CWD
+        )
+
+    def test_run_cron_with_variable(self) -> None:
+        crontab = Crontab(self.nodes)
+        crontab.run(crontab.jobs[2])
+        cronrunner.subprocess.Popen.assert_called_with(
+            [Crontab.DEFAULT_SHELL, "-c", "FOO=bar;echo $FOO"], **CWD
+        )
+
+    def test_run_cron_after_variable_but_not_stuck_to_it(self) -> None:
+        crontab = Crontab(self.nodes)
+        crontab.run(crontab.jobs[3])
+        cronrunner.subprocess.Popen.assert_called_with(
+            [Crontab.DEFAULT_SHELL, "-c", "FOO=bar;:"], **CWD
+        )
+
+    def test_run_cron_with_default_shell(self) -> None:
+        crontab = Crontab(self.nodes)
+        crontab.run(crontab.jobs[0])
+        self.assertEqual(
+            cronrunner.subprocess.Popen.call_args.args[0][0], Crontab.DEFAULT_SHELL
+        )
+
+    def test_run_cron_with_different_shell(self) -> None:
+        crontab = Crontab(self.nodes)
+        crontab.run(crontab.jobs[4])
+        self.assertEqual(cronrunner.subprocess.Popen.call_args.args[0][0], "/bin/bash")
+        cronrunner.subprocess.Popen.assert_called_with(
+            ["/bin/bash", "-c", "FOO=bar;SHELL=/bin/bash;echo 'I am echoed by bash!'"],
+            **CWD,
+        )
+
+    def test_shell_is_reset_between_two_executions(self) -> None:
+        crontab = Crontab(self.nodes)
+
+        crontab.run(crontab.jobs[4])
+        self.assertEqual(cronrunner.subprocess.Popen.call_count, 1)
+        self.assertEqual(cronrunner.subprocess.Popen.call_args.args[0][0], "/bin/bash")
+
+        crontab.run(crontab.jobs[0])
+        self.assertEqual(cronrunner.subprocess.Popen.call_count, 2)
+        self.assertEqual(
+            cronrunner.subprocess.Popen.call_args.args[0][0], Crontab.DEFAULT_SHELL
+        )
+
+    def test_run_cron_with_wrapper(self) -> None:
+        crontab = Crontab(self.nodes)
+        crontab.run(crontab.jobs[0], ["nice", "-n", "10"])
+        cronrunner.subprocess.Popen.assert_called_with(
+            [
+                "nice",
+                "-n",
+                "10",
+                Crontab.DEFAULT_SHELL,
+                "-c",
+                "/usr/bin/bash ~/startup.sh",
+            ],
+            **CWD,
+        )
+
+    def test_run_cron_as_another_user(self) -> None:
+        crontab = Crontab(self.nodes)
+        crontab.run(crontab.jobs[0], cronrunner._make_user_switch_wrapper("backup"))
+        cronrunner.subprocess.Popen.assert_called_with(
+            [
+                "sudo",
+                "-u",
+                "backup",
+                "--",
+                Crontab.DEFAULT_SHELL,
+                "-c",
+                "/usr/bin/bash ~/startup.sh",
+            ],
+            **CWD,
+        )
+
+    def test_variables_in_scope(self) -> None:
+        crontab = Crontab(self.nodes)
+        self.assertDictEqual(crontab.variables_in_scope(crontab.jobs[0]), {})
+        self.assertDictEqual(
+            crontab.variables_in_scope(crontab.jobs[2]), {"FOO": "bar"}
+        )
+        self.assertDictEqual(
+            crontab.variables_in_scope(crontab.jobs[4]),
+            {"FOO": "bar", "SHELL": "/bin/bash"},
+        )
+
+    def test_variables_in_scope_last_declaration_wins(self) -> None:
+        job = CronJob(schedule="* * * * *", job="echo $FOO", description="")
+        crontab = Crontab(
+            [
+                Variable(identifier="FOO", value="bar"),
+                Variable(identifier="FOO", value="baz"),
+                job,
+                Variable(identifier="FOO", value="qux"),
+            ]
+        )
+        self.assertDictEqual(crontab.variables_in_scope(job), {"FOO": "baz"})
+
+    def test_run_cron_sourcing_profile(self) -> None:
+        crontab = Crontab(self.nodes)
+        crontab.run(crontab.jobs[2], profile="/home/me/my profile")
+        cronrunner.subprocess.Popen.assert_called_with(
+            [Crontab.DEFAULT_SHELL, "-c", ". '/home/me/my profile';FOO=bar;echo $FOO"],
+            **CWD,
+        )
+
+    def test_run_cron_with_env_from_job(self) -> None:
+        borrower = CronJob(schedule="@daily", job="echo $FOO $BAZ", description="")
+        lender = CronJob(schedule="@daily", job="lender", description="")
+        crontab = Crontab(
+            [
+                Variable(identifier="BAZ", value="1"),
+                borrower,
+                Variable(identifier="FOO", value="bar"),
+                Variable(identifier="BAZ", value="2"),
+                lender,
+            ]
+        )
+        crontab.run(borrower, env_from=lender)
+        cronrunner.subprocess.Popen.assert_called_with(
+            [Crontab.DEFAULT_SHELL, "-c", "BAZ=2;FOO=bar;BAZ=1;echo $FOO $BAZ"],
+            **CWD,
+        )
+
+    def test_borrowed_declarations(self) -> None:
+        lender = CronJob(schedule="@daily", job="lender", description="")
+        crontab = Crontab(
+            [
+                Variable(identifier="FOO", value="bar"),
+                Variable(identifier="FOO", value="baz"),
+                lender,
+                Variable(identifier="AFTER", value="1"),
+            ]
+        )
+        self.assertListEqual(crontab.borrowed_declarations(lender), ["FOO=baz"])
+
+    def test_run_cron_with_umask(self) -> None:
+        job = CronJob(schedule="@daily", job=":", description="", umask=0o077)
+        crontab = Crontab([job])
+        umasks: list = []
+
+        def record_umask(*args, **kwargs) -> Mock:
+            umask: int = cronrunner.os.umask(0)
+            cronrunner.os.umask(umask)
+            umasks.append(umask)
+            return Mock(**{"wait.return_value": 0})
+
+        cronrunner.subprocess.Popen.side_effect = record_umask
+        previous_umask: int = cronrunner.os.umask(0o022)
+        try:
+            crontab.run(job)
+            self.assertListEqual(umasks, [0o077])
+            self.assertEqual(cronrunner.os.umask(0o022), 0o022)
+        finally:
+            cronrunner.os.umask(previous_umask)
+
+    def test_command_available(self) -> None:
+        crontab = Crontab([CronJob("@daily", "ls -l", "")])
+        with patch.object(cronrunner.shutil, "which", return_value="/bin/ls"):
+            self.assertIsNone(crontab.check_command_available(crontab.jobs[0]))
+
+    def test_command_not_available(self) -> None:
+        crontab = Crontab([CronJob("@daily", "FOO=bar not-a-command --flag", "")])
+        with patch.object(cronrunner.shutil, "which", return_value=None):
+            self.assertEqual(
+                crontab.check_command_available(crontab.jobs[0]), "not-a-command"
+            )
+
+    def test_builtin_command_is_always_available(self) -> None:
+        crontab = Crontab(
+            [
+                CronJob("@daily", "cd /tmp && ./run.sh", ""),
+                CronJob("@daily", "echo 'hello'", ""),
+                CronJob("@daily", ":", ""),
+            ]
+        )
+        with patch.object(cronrunner.shutil, "which", return_value=None) as which:
+            for job in crontab.jobs:
+                self.assertIsNone(crontab.check_command_available(job))
+            which.assert_not_called()
+
+    def test_paths_and_expansions_are_not_checked(self) -> None:
+        crontab = Crontab(
+            [
+                CronJob("@daily", "/usr/local/bin/backup", ""),
+                CronJob("@daily", "$EDITOR ~/notes", ""),
+                CronJob("@daily", "echo 'unbalanced", ""),
+            ]
+        )
+        with patch.object(cronrunner.shutil, "which", return_value=None) as which:
+            for job in crontab.jobs:
+                self.assertIsNone(crontab.check_command_available(job))
+            which.assert_not_called()
+
+    def test_command_is_looked_up_in_crontab_path(self) -> None:
+        crontab = Crontab(
+            [
+                Variable(identifier="PATH", value="/opt/bin:/usr/bin"),
+                CronJob("@daily", "backup", ""),
+            ]
+        )
+        with patch.object(cronrunner.shutil, "which", return_value=None) as which:
+            crontab.check_command_available(crontab.jobs[0])
+        which.assert_called_once_with("backup", path="/opt/bin:/usr/bin")
+
+    def test_quotes_around_crontab_path_are_not_part_of_it(self) -> None:
+        crontab = Crontab(
+            [
+                Variable(identifier="PATH", value='"/opt/bin:/usr/bin"'),
+                CronJob("@daily", "backup", ""),
+            ]
+        )
+        with patch.object(cronrunner.shutil, "which", return_value=None) as which:
+            crontab.check_command_available(crontab.jobs[0])
+        which.assert_called_once_with("backup", path="/opt/bin:/usr/bin")
+
+    def test_shell_arg_for_common_shells(self) -> None:
+        self.assertEqual(Crontab.shell_arg_for("sh"), "-c")
+        self.assertEqual(Crontab.shell_arg_for("/bin/sh"), "-c")
+        self.assertEqual(Crontab.shell_arg_for("/usr/bin/bash"), "-c")
+        self.assertEqual(Crontab.shell_arg_for("/usr/bin/pwsh"), "-Command")
+        self.assertEqual(Crontab.shell_arg_for("unknown-shell"), "-c")
+
+    def test_run_cron_with_shell_arg_guessed_from_shell(self) -> None:
+        job = CronJob(schedule="@daily", job="Get-Date", description="")
+        crontab = Crontab([Variable(identifier="SHELL", value="/usr/bin/pwsh"), job])
+        crontab.run(job)
+        cronrunner.subprocess.Popen.assert_called_with(
+            ["/usr/bin/pwsh", "-Command", "$env:SHELL='/usr/bin/pwsh';Get-Date"],
+            **CWD,
+        )
+
+    def test_run_cron_with_powershell_syntax(self) -> None:
+        job = CronJob(schedule="@daily", job="Write-Output $env:FOO", description="")
+        crontab = Crontab(
+            [
+                Variable(identifier="FOO", value="\"it's\""),
+                Variable(identifier="SHELL", value="pwsh"),
+                job,
+            ]
+        )
+        crontab.run(job, profile="/home/me/it's.ps1")
+        cronrunner.subprocess.Popen.assert_called_with(
+            [
+                "pwsh",
+                "-Command",
+                ". '/home/me/it''s.ps1';$env:FOO='it''s';$env:SHELL='pwsh';"
+                "Write-Output $env:FOO",
+            ],
+            **CWD,
+        )
+
+    def test_run_cron_with_cmd_syntax(self) -> None:
+        job = CronJob(schedule="@daily", job="echo %FOO%", description="")
+        crontab = Crontab(
+            [
+                Variable(identifier="FOO", value="'a b'"),
+                Variable(identifier="SHELL", value="C:\\Windows\\cmd.exe"),
+                job,
+            ]
+        )
+        crontab.run(job)
+        cronrunner.subprocess.Popen.assert_called_with(
+            [
+                "C:\\Windows\\cmd.exe",
+                "/C",
+                'set "FOO=a b"&set "SHELL=C:\\Windows\\cmd.exe"&echo %FOO%',
+            ],
+            **CWD,
+        )
+
+    def test_run_cron_with_shell_arg_override(self) -> None:
+        crontab = Crontab(self.nodes)
+        crontab.run(crontab.jobs[0], shell_arg="-ec")
+        cronrunner.subprocess.Popen.assert_called_with(
+            [Crontab.DEFAULT_SHELL, "-ec", "/usr/bin/bash ~/startup.sh"], **CWD
+        )
+
+    def test_run_cron_as_script(self) -> None:
+        crontab = Crontab(self.nodes)
+        scripts: dict = {}
+
+        def read_script(command: list, **kwargs) -> Mock:
+            scripts[command[1]] = Path(command[1]).read_text()
+            return Mock(**{"wait.return_value": 0})
+
+        cronrunner.subprocess.Popen.side_effect = read_script
+        crontab.run(crontab.jobs[2], as_script=True)
+
+        command: list = cronrunner.subprocess.Popen.call_args.args[0]
+        self.assertEqual(command[0], Crontab.DEFAULT_SHELL)
+        self.assertEqual(len(command), 2)
+        self.assertDictEqual(scripts, {command[1]: "FOO=bar\necho $FOO\n"})
+        self.assertEqual(cronrunner.subprocess.Popen.call_args.kwargs, CWD)
+        self.assertFalse(Path(command[1]).exists())
+
+    def test_run_cron_as_script_for_real(self) -> None:
+        with tempfile.TemporaryDirectory() as directory:
+            output: Path = Path(directory) / "output"
+            job = CronJob("@daily", f"echo \"$FOO\" > '{output}'", "")
+            crontab = Crontab([Variable(identifier="FOO", value="'it works'"), job])
+            cronrunner.subprocess.Popen = SUBPROCESS_POPEN
+            crontab.run(job, as_script=True)
+            self.assertEqual(output.read_text(), "it works\n")
+
+    def test_script_is_removed_even_if_run_fails(self) -> None:
+        crontab = Crontab(self.nodes)
+        cronrunner.subprocess.Popen.side_effect = OSError
+        with self.assertRaises(OSError):
+            crontab.run(crontab.jobs[0], as_script=True)
+        script: str = cronrunner.subprocess.Popen.call_args.args[0][1]
+        self.assertFalse(Path(script).exists())
+
+    def test_run_result(self) -> None:
+        cronrunner.subprocess.Popen.return_value.wait.return_value = 3
+        crontab = Crontab(self.nodes)
+        result: RunResult = crontab.run(crontab.jobs[0])
+        self.assertEqual(result.exit_code, 3)
+
+    def test_run_result_pid(self) -> None:
+        cronrunner.subprocess.Popen.return_value.pid = 1234
+        crontab = Crontab(self.nodes)
+        self.assertEqual(crontab.run(crontab.jobs[0]).pid, 1234)
+
+    def test_run_result_pid_for_real(self) -> None:
+        job = CronJob("@daily", 'exit "$(($$ % 256))"', "")
+        crontab = Crontab([job])
+        cronrunner.subprocess.Popen = SUBPROCESS_POPEN
+        result: RunResult = crontab.run(job)
+        self.assertEqual(result.exit_code, result.pid % 256)
+
+    @unittest.skipIf(cronrunner.resource is None, "Unix only")
+    def test_run_result_resource_usage(self) -> None:
+        job = CronJob("@daily", "i=0; while [ $i -lt 10000 ]; do i=$((i+1)); done", "")
+        crontab = Crontab([job])
+        cronrunner.subprocess.Popen = SUBPROCESS_POPEN
+        result: RunResult = crontab.run(job)
+        self.assertEqual(result.exit_code, 0)
+        self.assertIsInstance(result.resource_usage, ResourceUsage)
+        self.assertGreater(
+            result.resource_usage.user_time + result.resource_usage.system_time, 0
+        )
+
+    def test_run_cron_with_timeout(self) -> None:
+        job = CronJob("@daily", "exec sleep 5", "")
+        crontab = Crontab([job])
+        cronrunner.subprocess.Popen = SUBPROCESS_POPEN
+        result: RunResult = crontab.run(job, timeout=0.1)
+        self.assertTrue(result.timed_out)
+        self.assertEqual(result.exit_code, -signal.SIGTERM)
+
+    @unittest.skipIf(not Path("/proc").is_dir(), "needs /proc")
+    def test_run_cron_with_timeout_terminates_whole_job(self) -> None:
+        with tempfile.TemporaryDirectory() as directory:
+            pid_file = Path(directory) / "pid"
+            job = CronJob("@daily", f"sleep 5 & echo $! > {pid_file}; wait", "")
+            crontab = Crontab([job])
+            cronrunner.subprocess.Popen = SUBPROCESS_POPEN
+            result: RunResult = crontab.run(job, timeout=0.5)
+            pid: int = int(pid_file.read_text())
+        self.assertTrue(result.timed_out)
+        self.assertTrue(wait_until_gone(pid), "sleep survived the timeout")
+
+    def test_run_cron_killed_after_grace_period(self) -> None:
+        job = CronJob("@daily", "trap '' TERM; sleep 1", "")
+        crontab = Crontab([job])
+        cronrunner.subprocess.Popen = SUBPROCESS_POPEN
+        with patch.object(Crontab, "TIMEOUT_GRACE_PERIOD", 0.1):
+            result: RunResult = crontab.run(job, timeout=0.1)
+        self.assertTrue(result.timed_out)
+        self.assertEqual(result.exit_code, -signal.SIGKILL)
+
+    def test_run_cron_within_timeout(self) -> None:
+        job = CronJob("@daily", "exit 3", "")
+        crontab = Crontab([job])
+        cronrunner.subprocess.Popen = SUBPROCESS_POPEN
+        self.assertEqual(
+            crontab.run(job, timeout=5), RunResult(3, ANY, False, ANY, ANY, ANY)
+        )
+
+    def test_run_records_start_and_end_times(self) -> None:
+        job = CronJob("@daily", "sleep 0.1", "")
+        crontab = Crontab([job])
+        cronrunner.subprocess.Popen = SUBPROCESS_POPEN
+        before: datetime = datetime.now().astimezone()
+        result: RunResult = crontab.run(job)
+        self.assertIsNotNone(result.started_at.tzinfo)
+        self.assertLessEqual(before, result.started_at)
+        self.assertLess(result.started_at, result.ended_at)
+        self.assertGreaterEqual(result.duration, 0.1)
+
+    def test_duration_is_unknown_without_times(self) -> None:
+        self.assertIsNone(RunResult(0).duration)
+
+    def test_check_syntax(self) -> None:
+        job = CronJob("@daily", "echo 'ok'", "")
+        crontab = Crontab([job])
+        cronrunner.subprocess.run = SUBPROCESS_RUN
+        cronrunner.subprocess.Popen = SUBPROCESS_POPEN
+        self.assertEqual(crontab.check_syntax(job), RunResult(0))
+
+    def test_check_syntax_error(self) -> None:
+        job = CronJob("@daily", "echo 'unbalanced", "")
+        crontab = Crontab([job])
+        cronrunner.subprocess.run = partial(SUBPROCESS_RUN, stderr=subprocess.DEVNULL)
+        cronrunner.subprocess.Popen = SUBPROCESS_POPEN
+        result: RunResult = crontab.check_syntax(job)
+        self.assertNotEqual(result.exit_code, 0)
+
+    def test_check_syntax_does_not_run_job(self) -> None:
+        with tempfile.TemporaryDirectory() as directory:
+            output: Path = Path(directory) / "output"
+            job = CronJob("@daily", f"touch '{output}'", "")
+            crontab = Crontab([job])
+            cronrunner.subprocess.run = SUBPROCESS_RUN
+            cronrunner.subprocess.Popen = SUBPROCESS_POPEN
+            crontab.check_syntax(job)
+            self.assertFalse(output.exists())
+
+    def test_check_syntax_unsupported_shell(self) -> None:
+        job = CronJob("@daily", "Get-Date", "")
+        crontab = Crontab([Variable(identifier="SHELL", value="/usr/bin/pwsh"), job])
+        self.assertIsNone(crontab.check_syntax(job))
+        cronrunner.subprocess.run.assert_not_called()
+
+    def test_resolve_default_shell(self) -> None:
+        crontab = Crontab(self.nodes)
+        self.assertEqual(crontab.resolve_shell(crontab.jobs[0]), Crontab.DEFAULT_SHELL)
+
+    def test_resolve_different_shell(self) -> None:
+        crontab = Crontab(self.nodes)
+        self.assertEqual(crontab.resolve_shell(crontab.jobs[4]), "/bin/bash")
+        cronrunner.subprocess.run.assert_not_called()
+
+    def test_run_job_not_in_crontab(self) -> None:
+        crontab = Crontab(self.nodes)
+        with self.assertRaises(ValueError):
+            crontab.run(CronJob(schedule="", job="", description=""))
+
+
+class StubSource:
+    def __init__(self, crontab: str) -> None:
+        self.crontab: str = crontab
+
+    def read(self) -> str:
+        return self.crontab
+
+
+class TestGetCrontab(unittest.TestCase):
+    def test_crontab_from_source(self) -> None:
+        crontab: Crontab = cronrunner.get_crontab_from_source(
+            StubSource("FOO=bar\r\n## Print variable.\r\n* * * * * echo $FOO\r\n")
+        )
+        self.assertListEqual(
+            crontab.jobs,
+            [CronJob("* * * * *", "echo $FOO", "Print variable.")],
+        )
+        self.assertEqual(crontab.source_line(crontab.jobs[0]), "* * * * * echo $FOO")
+
+    def test_source_line_of_continued_job(self) -> None:
+        crontab: Crontab = cronrunner.get_crontab_from_source(
+            StubSource("FOO=bar\n* * * * * echo  a \\\n  b\n@daily :\n")
+        )
+        self.assertEqual(
+            crontab.source_line(crontab.jobs[0]), "* * * * * echo  a \\\n  b"
+        )
+        self.assertEqual(crontab.source_line(crontab.jobs[1]), "@daily :")
+
+    def test_source_line_without_source(self) -> None:
+        job = CronJob("@daily", ":", "")
+        with self.assertRaises(ValueError):
+            Crontab([job]).source_line(job)
+
+    def test_crontab_from_source_with_parser(self) -> None:
+        crontab: Crontab = cronrunner.get_crontab_from_source(
+            StubSource("#@umask 077\n@daily :"), CrontabParser(umask_directives=True)
+        )
+        self.assertEqual(crontab.jobs[0].umask, 0o077)
+
+    def test_source_errors_are_propagated(self) -> None:
+        class FailingSource:
+            def read(self) -> str:
+                raise CrontabReadError("Cannot read crontab.")
+
+        with self.assertRaises(CrontabReadError):
+            cronrunner.get_crontab_from_source(FailingSource())
+
+    def test_crontab_from_file(self) -> None:
+        with tempfile.TemporaryDirectory() as tmp:
+            path = Path(tmp) / "crontab"
+            path.write_text("## Say hello.\r\n@daily echo hello\r\n")
+            crontab: Crontab = cronrunner.get_crontab_from_file(path)
+        self.assertListEqual(
+            crontab.jobs, [CronJob("@daily", "echo hello", "Say hello.")]
+        )
+
+    def test_crontab_from_stdin(self) -> None:
+        with patch("sys.stdin", io.StringIO("@daily echo hello\n")):
+            crontab: Crontab = cronrunner.get_crontab_from_file(Path("-"))
+        self.assertListEqual(crontab.jobs, [CronJob("@daily", "echo hello", "")])
+
+    def test_crontab_from_missing_file(self) -> None:
+        with tempfile.TemporaryDirectory() as tmp:
+            with self.assertRaises(CrontabReadError) as ctx:
+                cronrunner.get_crontab_from_file(Path(tmp) / "missing")
+        self.assertIn("No such file or directory", str(ctx.exception))
+
+
+class TestFormatResourceUsage(unittest.TestCase):
+    def test_format(self) -> None:
+        self.assertEqual(
+            cronrunner._format_resource_usage(ResourceUsage(1.5, 0.0123)),
+            "user 1.500s, system 0.012s",
+        )
+
+
+class TestFormatTsv(unittest.TestCase):
+    def test_header_and_rows(self) -> None:
+        job = CronJob("@daily", "backup.sh", "")
+        fingerprint: int = job.content_fingerprint(include_schedule=True)
+        self.assertEqual(
+            cronrunner._format_tsv(Crontab([Variable("FOO", "bar"), job])),
+            "id\tfingerprint\tschedule\tcommand\n"
+            f"1\t{fingerprint:x}\t@daily\tbackup.sh",
+        )
+
+    def test_tabs_are_replaced_with_spaces(self) -> None:
+        job = CronJob("@daily", "printf 'a\tb'", "")
+        row: str = cronrunner._format_tsv(Crontab([job])).splitlines()[1]
+        self.assertEqual(row.split("\t")[3], "printf 'a b'")
+
+
+class TestFormatYaml(unittest.TestCase):
+    def test_jobs(self) -> None:
+        jobs: list = [
+            CronJob("@daily", "backup.sh", "Back up."),
+            CronJob("* * * * *", 'echo "a: b"\\n', ""),
+        ]
+        fingerprints: list = [
+            f"{job.content_fingerprint(include_schedule=True):x}" for job in jobs
+        ]
+        self.assertEqual(
+            cronrunner._format_yaml(Crontab([Variable("FOO", "bar"), *jobs])),
+            "- id: 1\n"
+            f'  fingerprint: "{fingerprints[0]}"\n'
+            '  schedule: "@daily"\n'
+            '  command: "backup.sh"\n'
+            '  description: "Back up."\n'
+            "- id: 2\n"
+            f'  fingerprint: "{fingerprints[1]}"\n'
+            '  schedule: "* * * * *"\n'
+            '  command: "echo \\"a: b\\"\\\\n"\n'
+            '  description: ""',
+        )
+
+    def test_no_jobs(self) -> None:
+        self.assertEqual(cronrunner._format_yaml(Crontab([])), "[]")
+
+
+class TestSnapshot(unittest.TestCase):
+    def setUp(self) -> None:
+        self.jobs: list = [
+            CronJob("@daily", "backup.sh", ""),
+            CronJob("@hourly", "sync.sh", ""),
+        ]
+        self.snapshot: set = set(Crontab(self.jobs).fingerprint_snapshot())
+
+    def test_fingerprint_snapshot(self) -> None:
+        self.assertListEqual(
+            Crontab(self.jobs).fingerprint_snapshot(),
+            [job.content_fingerprint(include_schedule=True) for job in self.jobs],
+        )
+
+    def test_no_duplicate_fingerprints(self) -> None:
+        self.assertListEqual(Crontab(self.jobs).duplicate_fingerprints(), [])
+
+    def test_duplicate_fingerprints(self) -> None:
+        crontab = Crontab([*self.jobs, *self.jobs, self.jobs[0]])
+        self.assertListEqual(
+            crontab.duplicate_fingerprints(), crontab.fingerprint_snapshot()[:2]
+        )
+
+    def test_duplicate_fingerprints_from_collision(self) -> None:
+        with patch.dict(cronrunner.HASH_FUNCTIONS, {"const": lambda _: 42}):
+            self.assertListEqual(
+                Crontab(self.jobs).duplicate_fingerprints("const"), [42]
+            )
+
+    def test_unchanged(self) -> None:
+        self.assertListEqual(
+            cronrunner._changed_jobs(Crontab(self.jobs), self.snapshot), []
+        )
+
+    def test_added_job(self) -> None:
+        new_job = CronJob("@weekly", "cleanup.sh", "")
+        crontab = Crontab([*self.jobs, new_job])
+        self.assertListEqual(
+            cronrunner._changed_jobs(crontab, self.snapshot), [(3, new_job)]
+        )
+
+    def test_removed_job(self) -> None:
+        crontab = Crontab(self.jobs[1:])
+        self.assertListEqual(cronrunner._changed_jobs(crontab, self.snapshot), [])
+
+    def test_changed_command(self) -> None:
+        changed_job = CronJob("@daily", "backup.sh --full", "")
+        crontab = Crontab([changed_job, self.jobs[1]])
+        self.assertListEqual(
+            cronrunner._changed_jobs(crontab, self.snapshot), [(1, changed_job)]
+        )
+
+    def test_changed_schedule(self) -> None:
+        changed_job = CronJob("@weekly", "sync.sh", "")
+        crontab = Crontab([self.jobs[0], changed_job])
+        self.assertListEqual(
+            cronrunner._changed_jobs(crontab, self.snapshot), [(2, changed_job)]
+        )
+
+    def test_write_and_read_snapshot(self) -> None:
+        fingerprints: list = Crontab(self.jobs).fingerprint_snapshot()
+        with tempfile.TemporaryDirectory() as directory:
+            path: Path = Path(directory) / "snapshot"
+            cronrunner._write_snapshot(path, fingerprints)
+            self.assertSetEqual(cronrunner._read_snapshot(path), set(fingerprints))
+
+    def test_read_invalid_snapshot(self) -> None:
+        with tempfile.TemporaryDirectory() as directory:
+            path: Path = Path(directory) / "snapshot"
+            path.write_text("not a snapshot\n")
+            with self.assertRaises(ValueError):
+                cronrunner._read_snapshot(path)
+
+
+class TestExitMessage(unittest.TestCase):
+    def test_parse(self) -> None:
+        self.assertEqual(
+            cronrunner._exit_message("2=Config error"), (2, "Config error")
+        )
+        self.assertEqual(cronrunner._exit_message("1=a=b"), (1, "a=b"))
+
+    def test_parse_invalid(self) -> None:
+        for value in ("2", "x=Config error", "=Config error"):
+            with self.subTest(value=value), self.assertRaises(
+                argparse.ArgumentTypeError
+            ):
+                cronrunner._exit_message(value)
+
+    def test_matching_exit_code(self) -> None:
+        self.assertEqual(
+            cronrunner._get_exit_message([(2, "Config error")], 2), "Config error"
+        )
+
+    def test_non_matching_exit_code(self) -> None:
+        self.assertIsNone(cronrunner._get_exit_message([(2, "Config error")], 1))
+
+    def test_last_message_wins(self) -> None:
+        self.assertEqual(
+            cronrunner._get_exit_message([(2, "First"), (2, "Second")], 2), "Second"
+        )
+
+
+class TestJobFilter(unittest.TestCase):
+    def test_substring_matches_command_or_description(self) -> None:
+        job_filter = cronrunner._make_job_filter("backup")
+        self.assertTrue(job_filter(CronJob("@daily", "backup.sh", "")))
+        self.assertTrue(job_filter(CronJob("@daily", "sync.sh", "Backup photos.")))
+        self.assertFalse(job_filter(CronJob("@daily", "sync.sh", "Sync photos.")))
+
+    def test_substring_is_not_a_regex(self) -> None:
+        job_filter = cronrunner._make_job_filter("a.sh")
+        self.assertTrue(job_filter(CronJob("@daily", "a.sh", "")))
+        self.assertFalse(job_filter(CronJob("@daily", "ab.sh", "")))
+
+    def test_regex(self) -> None:
+        job_filter = cronrunner._make_job_filter(r"^(backup|sync)\.sh$", regex=True)
+        self.assertTrue(job_filter(CronJob("@daily", "sync.sh", "")))
+        self.assertFalse(job_filter(CronJob("@daily", "rsync.sh", "")))
+
+    def test_invalid_regex(self) -> None:
+        with self.assertRaises(re.error):
+            cronrunner._make_job_filter("(", regex=True)
+
+    def test_schedule_shortcut(self) -> None:
+        schedule_filter = cronrunner._make_schedule_filter("@daily")
+        self.assertTrue(schedule_filter(CronJob("@daily", "backup.sh", "")))
+        self.assertFalse(schedule_filter(CronJob("@hourly", "@daily.sh", "")))
+
+    def test_schedule_fields(self) -> None:
+        schedule_filter = cronrunner._make_schedule_filter("* *")
+        self.assertTrue(schedule_filter(CronJob("30 4 * * *", ":", "")))
+        self.assertFalse(schedule_filter(CronJob("30 4 1 * 0", ":", "")))
+
+    def test_combine_filters(self) -> None:
+        job_filter = cronrunner._combine_filters(
+            [
+                cronrunner._make_schedule_filter("@daily"),
+                cronrunner._make_job_filter("backup"),
+            ]
+        )
+        self.assertTrue(job_filter(CronJob("@daily", "backup.sh", "")))
+        self.assertFalse(job_filter(CronJob("@daily", "sync.sh", "")))
+        self.assertFalse(job_filter(CronJob("@hourly", "backup.sh", "")))
+
+
+class TestAuditLog(unittest.TestCase):
+    def setUp(self) -> None:
+        self.started_at = datetime(2024, 3, 1, 12, 30, 5, 123, tzinfo=timezone.utc)
+        self.ended_at = self.started_at + timedelta(seconds=1.23456)
+        self.job = CronJob("@daily", "echo 'a\tb'\necho c", "")
+        self.fingerprint: str = f"{self.job.content_fingerprint('djb2', True):x}"
+
+    def test_format_audit_line(self) -> None:
+        result = RunResult(2, started_at=self.started_at, ended_at=self.ended_at)
+        self.assertEqual(
+            cronrunner._format_audit_line("alice", 3, self.job, result),
+            f"2024-03-01T12:30:05+00:00\talice\t3\t{self.fingerprint}"
+            "\t2\t1.235\techo 'a b' echo c",
+        )
+
+    def test_format_audit_line_with_hash_algo(self) -> None:
+        line: str = cronrunner._format_audit_line(
+            "alice", 3, self.job, RunResult(0), "fnv"
+        )
+        self.assertEqual(
+            line.split("\t")[3], self.job.fingerprint_hex("fnv", include_schedule=True)
+        )
+
+    def test_format_audit_line_with_unknown_values(self) -> None:
+        line: str = cronrunner._format_audit_line("alice", 3, self.job, RunResult(0))
+        columns: list = line.split("\t")
+        self.assertEqual(columns[0], "-")
+        self.assertEqual(columns[5], "-")
+
+    def test_append_audit_line(self) -> None:
+        with tempfile.TemporaryDirectory() as tmp:
+            path = Path(tmp) / "audit.log"
+            cronrunner._append_audit_line(path, "first")
+            cronrunner._append_audit_line(path, "second")
+            self.assertEqual(path.read_text(), "first\nsecond\n")
+
+    def test_append_audit_line_error_only_warns(self) -> None:
+        stdout = io.StringIO()
+        with tempfile.TemporaryDirectory() as tmp, redirect_stdout(stdout):
+            cronrunner._append_audit_line(Path(tmp) / "missing" / "audit.log", "x")
+        self.assertIn("Warning: cannot write audit log", stdout.getvalue())
+
+
+class TestCheckCrontab(unittest.TestCase):
+    def test_regular_schedule_spacing(self) -> None:
+        job = CronJob("* * * * *", "echo  'a  b'", "")
+        self.assertFalse(
+            cronrunner._has_irregular_schedule_spacing(
+                "  * * * * * echo  'a  b'  ", job
+            )
+        )
+        job = CronJob("@daily", ":", "")
+        self.assertFalse(cronrunner._has_irregular_schedule_spacing("@daily\t:", job))
+
+    def test_irregular_schedule_spacing(self) -> None:
+        job = CronJob("* * * * *", "echo", "")
+        self.assertTrue(
+            cronrunner._has_irregular_schedule_spacing("*\t* * * * echo", job)
+        )
+        self.assertTrue(
+            cronrunner._has_irregular_schedule_spacing("* * * *  * echo", job)
+        )
+
+    def test_schedule_spacing_of_continued_line(self) -> None:
+        job = CronJob("* * * * *", "echo a   b", "")
+        self.assertTrue(
+            cronrunner._has_irregular_schedule_spacing("*  * * * * echo a \\\n  b", job)
+        )
+        self.assertFalse(
+            cronrunner._has_irregular_schedule_spacing("* * * * * echo a \\\n  b", job)
+        )
+        self.assertTrue(
+            cronrunner._has_irregular_schedule_spacing("* * \\\n  * * * echo", job)
+        )
+
+    def test_schedule_spacing_of_no_log_job(self) -> None:
+        job = CronJob("* * * * *", "echo", "", no_log=True)
+        self.assertTrue(
+            cronrunner._has_irregular_schedule_spacing("-*\t* * * * echo", job)
+        )
+
+    def test_irregular_schedule_spacing_is_a_note(self) -> None:
+        source: str = "*\t*  * * * echo foo\n* * * * * echo bar\n"
+        crontab = Crontab(CrontabParser().parse(source), source)
+        problems, notes = cronrunner._check_crontab(crontab)
+        self.assertListEqual(problems, [])
+        self.assertListEqual(
+            notes,
+            [
+                "Irregular spacing in schedule, read as '* * * * *': "
+                "*\t*  * * * echo foo"
+            ],
+        )
+
+
+class TestPriorityWrapper(unittest.TestCase):
+    def setUp(self) -> None:
+        patcher = patch.object(cronrunner.shutil, "which", return_value="/usr/bin/x")
+        self.which: Mock = patcher.start()
+        self.addCleanup(patcher.stop)
+
+    def test_no_priority(self) -> None:
+        self.assertListEqual(cronrunner._make_priority_wrapper(None, False), [])
+
+    def test_nice(self) -> None:
+        self.assertListEqual(
+            cronrunner._make_priority_wrapper(10, False), ["nice", "-n", "10"]
+        )
+
+    def test_negative_nice(self) -> None:
+        self.assertListEqual(
+            cronrunner._make_priority_wrapper(-5, False), ["nice", "-n", "-5"]
+        )
+
+    def test_nice_zero_is_not_ignored(self) -> None:
+        self.assertListEqual(
+            cronrunner._make_priority_wrapper(0, False), ["nice", "-n", "0"]
+        )
+
+    def test_nice_and_ionice(self) -> None:
+        self.assertListEqual(
+            cronrunner._make_priority_wrapper(19, True),
+            ["nice", "-n", "19", "ionice", "-c3"],
+        )
+
+    def test_missing_tool_is_skipped_with_warning(self) -> None:
+        self.which.side_effect = lambda tool: None if tool == "ionice" else tool
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            wrapper: list = cronrunner._make_priority_wrapper(10, True)
+        self.assertListEqual(wrapper, ["nice", "-n", "10"])
+        self.assertIn("Cannot find 'ionice'", stdout.getvalue())
+
+    def test_nice_value_in_range(self) -> None:
+        self.assertEqual(cronrunner._nice_value("-20"), -20)
+        self.assertEqual(cronrunner._nice_value("19"), 19)
+
+    def test_nice_value_out_of_range(self) -> None:
+        with self.assertRaises(cronrunner.argparse.ArgumentTypeError):
+            cronrunner._nice_value("20")
+        with self.assertRaises(cronrunner.argparse.ArgumentTypeError):
+            cronrunner._nice_value("-21")
+
+
+class TestTerminalSize(unittest.TestCase):
+    def test_make_termsize_env(self) -> None:
+        self.assertDictEqual(
+            cronrunner._make_termsize_env(os.terminal_size((120, 40))),
+            {"COLUMNS": "120", "LINES": "40"},
+        )
+
+    def test_no_terminal(self) -> None:
+        with redirect_stdout(io.StringIO()):
+            self.assertIsNone(cronrunner._get_terminal_size())
+
+
+class TestScopeWrapper(unittest.TestCase):
+    def test_scope(self) -> None:
+        self.assertListEqual(
+            cronrunner._make_scope_wrapper(),
+            ["systemd-run", "--user", "--scope", "--quiet", "--"],
+        )
+
+    def test_scope_with_description(self) -> None:
+        self.assertListEqual(
+            cronrunner._make_scope_wrapper("Back up photos."),
+            [
+                "systemd-run",
+                "--user",
+                "--scope",
+                "--quiet",
+                "--description=Back up photos.",
+                "--",
+            ],
+        )
+
+
+class TestCollapseWhitespace(unittest.TestCase):
+    def test_spaces_and_tabs_are_collapsed(self) -> None:
         self.assertEqual(
-            cronrunner.subprocess.run.call_args.args[0][0], Crontab.DEFAULT_SHELL
+            cronrunner._collapse_whitespace("echo  \t foo   bar"), "echo foo bar"
         )
 
-    def test_run_job_not_in_crontab(self) -> None:
-        crontab = Crontab(self.nodes)
+    def test_single_spaces_are_unchanged(self) -> None:
+        self.assertEqual(cronrunner._collapse_whitespace("echo foo"), "echo foo")
+
+    def test_whitespace_in_quotes_is_preserved(self) -> None:
+        self.assertEqual(
+            cronrunner._collapse_whitespace("echo  'a   b'   \"c \\\"  d\""),
+            "echo 'a   b' \"c \\\"  d\"",
+        )
+
+    def test_escaped_whitespace_is_preserved(self) -> None:
+        self.assertEqual(
+            cronrunner._collapse_whitespace("ls  my\\  file"), "ls my\\  file"
+        )
+
+
+class TestShellNotice(unittest.TestCase):
+    def test_matching_shells(self) -> None:
+        self.assertEqual(cronrunner._make_shell_notice("/bin/bash", "/bin/bash"), "")
+
+    def test_unknown_user_shell(self) -> None:
+        self.assertEqual(cronrunner._make_shell_notice("/bin/sh", ""), "")
+
+    def test_differing_shells(self) -> None:
+        notice: str = cronrunner._make_shell_notice("/bin/dash", "/bin/bash")
+        self.assertIn("/bin/dash", notice)
+        self.assertIn("/bin/bash", notice)
+
+
+class TestFormatExports(unittest.TestCase):
+    def test_no_variables(self) -> None:
+        self.assertEqual(cronrunner._format_exports({}), "")
+
+    def test_exports_are_sorted(self) -> None:
+        self.assertEqual(
+            cronrunner._format_exports({"FOO": "bar", "BAR": "baz"}),
+            'export BAR="baz"\nexport FOO="bar"',
+        )
+
+    def test_value_with_spaces(self) -> None:
+        self.assertEqual(
+            cronrunner._format_exports({"FOO": "hello world"}),
+            'export FOO="hello world"',
+        )
+
+    def test_value_with_single_quotes(self) -> None:
+        self.assertEqual(
+            cronrunner._format_exports({"FOO": "it's", "BAR": "'it's'"}),
+            "export BAR='it'\\''s'\nexport FOO=\"it's\"",
+        )
+
+    def test_value_with_double_quotes(self) -> None:
+        self.assertEqual(
+            cronrunner._format_exports({"FOO": 'say "hi"'}),
+            'export FOO="say \\"hi\\""',
+        )
+
+    def test_values_are_expanded_like_when_running(self) -> None:
+        self.assertEqual(
+            cronrunner._format_exports({"FOO": "$HOME/bin", "BAR": "'$HOME'"}),
+            "export BAR='$HOME'\nexport FOO=\"$HOME/bin\"",
+        )
+
+    def test_surrounding_quotes_are_not_part_of_value(self) -> None:
+        self.assertEqual(
+            cronrunner._format_exports({"FOO": '" padded "', "BAR": "'x'"}),
+            "export BAR='x'\nexport FOO=\" padded \"",
+        )
+
+
+class TestEnvDiff(unittest.TestCase):
+    def test_effective_env_only_changes_exported_variables(self) -> None:
+        self.assertDictEqual(
+            cronrunner._effective_env(
+                {"FOO": "'bar'", "HOME": "'/root'"}, {"HOME": "/home/me", "X": "1"}
+            ),
+            {"HOME": "/root", "X": "1"},
+        )
+
+    def test_effective_env_with_exported_variables(self) -> None:
+        self.assertDictEqual(
+            cronrunner._effective_env(
+                {"FOO": "'bar'", "HOME": "/root"},
+                {"HOME": "/home/me", "X": "1"},
+                exported=True,
+            ),
+            {"FOO": "bar", "HOME": "/root", "X": "1"},
+        )
+
+    def test_diff(self) -> None:
+        self.assertListEqual(
+            cronrunner._diff_env(
+                {"HOME": "/home/me", "SAME": "1"},
+                {"HOME": "/root", "SAME": "1", "NEW": "y"},
+            ),
+            ["-HOME=/home/me", "+HOME=/root", "+NEW=y"],
+        )
+
+    def test_posix_shells_do_not_export_variables(self) -> None:
+        job = CronJob("@daily", ":", "")
+        crontab = Crontab([Variable("FOO", "bar"), job])
+        self.assertFalse(crontab.exports_variables(job))
+
+    def test_powershell_exports_variables(self) -> None:
+        job = CronJob("@daily", ":", "")
+        crontab = Crontab([Variable("SHELL", "pwsh"), job])
+        self.assertTrue(crontab.exports_variables(job))
+
+    def test_no_diff(self) -> None:
+        self.assertListEqual(cronrunner._diff_env({"A": "1"}, {"A": "1"}), [])
+
+
+class TestResolvePrompt(unittest.TestCase):
+    def test_default_prompt(self) -> None:
+        self.assertEqual(
+            cronrunner._resolve_prompt(None, {}), ">>> Select a job to run: "
+        )
+
+    def test_prompt_from_env(self) -> None:
+        self.assertEqual(
+            cronrunner._resolve_prompt(None, {"CRONRUNNER_PROMPT": "job? "}), "job? "
+        )
+
+    def test_prompt_from_flag(self) -> None:
+        self.assertEqual(cronrunner._resolve_prompt("> ", {}), "> ")
+
+    def test_flag_takes_precedence_over_env(self) -> None:
+        self.assertEqual(
+            cronrunner._resolve_prompt("> ", {"CRONRUNNER_PROMPT": "job? "}), "> "
+        )
+
+
+class TestUserWrapper(unittest.TestCase):
+    def test_no_wrapper(self) -> None:
+        self.assertListEqual(cronrunner._make_user_wrapper(None, {}), [])
+
+    def test_wrapper_from_flag(self) -> None:
+        self.assertListEqual(
+            cronrunner._make_user_wrapper("flock -n '/tmp/my lock'", {}),
+            ["flock", "-n", "/tmp/my lock"],
+        )
+
+    def test_wrapper_from_env(self) -> None:
+        self.assertListEqual(
+            cronrunner._make_user_wrapper(None, {"CRONRUNNER_WRAP": "chronic"}),
+            ["chronic"],
+        )
+
+    def test_flag_takes_precedence_over_env(self) -> None:
+        self.assertListEqual(
+            cronrunner._make_user_wrapper("", {"CRONRUNNER_WRAP": "chronic"}), []
+        )
+
+    def test_invalid_quoting(self) -> None:
         with self.assertRaises(ValueError):
-            crontab.run(CronJob(schedule="", job="", description=""))
+            cronrunner._make_user_wrapper("flock -n '/tmp/lock", {})
+
+
+class TestUserSwitchWrapper(unittest.TestCase):
+    def test_sudo(self) -> None:
+        self.assertListEqual(
+            cronrunner._make_user_switch_wrapper("www-data"),
+            ["sudo", "-u", "www-data", "--"],
+        )
+
+    def test_su(self) -> None:
+        self.assertListEqual(
+            cronrunner._make_user_switch_wrapper("www-data", "su"),
+            ["su", "-c", 'exec "$0" "$@"', "--", "www-data"],
+        )
+
+    def test_invalid_user_name(self) -> None:
+        for user in ("", "-u", "root; rm", "a b"):
+            with self.subTest(user=user), self.assertRaises(ValueError):
+                cronrunner._make_user_switch_wrapper(user)
+
+
+class TestRunCondition(unittest.TestCase):
+    def test_condition_met(self) -> None:
+        self.assertTrue(
+            cronrunner._is_condition_met(subprocess.CompletedProcess([], 0))
+        )
+
+    def test_condition_not_met(self) -> None:
+        self.assertFalse(
+            cronrunner._is_condition_met(subprocess.CompletedProcess([], 1))
+        )
+
+
+class TestColorTest(unittest.TestCase):
+    def test_every_style_is_shown(self) -> None:
+        sample: str = cronrunner._format_color_test()
+        self.assertIn("error:     \x1b[0;91mInvalid job number.\x1b[0m", sample)
+        self.assertIn("highlight: \x1b[0;92m1.\x1b[0m", sample)
+        self.assertIn("attenuate: \x1b[0;90m* * * * *\x1b[0m", sample)
+        self.assertIn("title:     \x1b[1mBackup database\x1b[0m", sample)
+
+
+class TestJobLock(unittest.TestCase):
+    def setUp(self) -> None:
+        self.job = CronJob(schedule="@daily", job="backup.sh", description="")
+        self.fingerprint: str = self.job.fingerprint_hex(include_schedule=True)
+
+    def test_lock_path_in_runtime_dir(self) -> None:
+        self.assertEqual(
+            cronrunner._make_lock_path(self.job, {"XDG_RUNTIME_DIR": "/run/user/1000"}),
+            Path(f"/run/user/1000/cronrunner-{self.fingerprint}.lock"),
+        )
+
+    @unittest.skipIf(cronrunner.fcntl is None, "Unix only")
+    def test_lock_path_in_temporary_dir(self) -> None:
+        self.assertEqual(
+            cronrunner._make_lock_path(self.job, {}),
+            Path(tempfile.gettempdir())
+            / f"cronrunner-{os.getuid()}"
+            / f"cronrunner-{self.fingerprint}.lock",
+        )
+
+    @unittest.skipIf(cronrunner.fcntl is None, "Unix only")
+    def test_lock_directory_is_created_private(self) -> None:
+        with tempfile.TemporaryDirectory() as directory:
+            path: Path = Path(directory) / "locks" / "job.lock"
+            cronrunner._acquire_lock(path).close()
+            self.assertEqual(path.parent.stat().st_mode & 0o777, 0o700)
+            self.assertEqual(path.stat().st_mode & 0o777, 0o600)
+
+    @unittest.skipIf(cronrunner.fcntl is None, "Unix only")
+    def test_lock_directory_must_be_private(self) -> None:
+        with tempfile.TemporaryDirectory() as directory:
+            os.chmod(directory, 0o777)
+            with self.assertRaises(PermissionError):
+                cronrunner._acquire_lock(Path(directory) / "job.lock")
+            self.assertFalse((Path(directory) / "job.lock").exists())
+
+    @unittest.skipIf(cronrunner.fcntl is None, "Unix only")
+    def test_lock_is_not_opened_through_symlink(self) -> None:
+        with tempfile.TemporaryDirectory() as directory:
+            target: Path = Path(directory) / "target"
+            (Path(directory) / "job.lock").symlink_to(target)
+            with self.assertRaises(OSError):
+                cronrunner._acquire_lock(Path(directory) / "job.lock")
+            self.assertFalse(target.exists())
+
+    @unittest.skipIf(cronrunner.fcntl is None, "Unix only")
+    def test_lock_is_exclusive(self) -> None:
+        with tempfile.TemporaryDirectory() as directory:
+            path: Path = Path(directory) / "job.lock"
+            lock: TextIO = cronrunner._acquire_lock(path)
+            with self.assertRaises(BlockingIOError):
+                cronrunner._acquire_lock(path)
+            lock.close()
+            cronrunner._acquire_lock(path).close()
+
+
+class TestBanner(unittest.TestCase):
+    def test_job_with_description(self) -> None:
+        job = CronJob(schedule="@daily", job="backup.sh", description="Backup")
+        self.assertEqual(cronrunner._make_banner(job), "\x1b[1m== Backup ==\x1b[0m")
+
+    def test_job_without_description(self) -> None:
+        job = CronJob(schedule="@daily", job="backup.sh", description="")
+        self.assertEqual(cronrunner._make_banner(job), "")
+
+
+class TestTheme(unittest.TestCase):
+    def setUp(self) -> None:
+        self.addCleanup(cronrunner._set_theme, "default")
+
+    def test_default(self) -> None:
+        cronrunner._set_theme("default")
+        self.assertEqual(cronrunner._color_highlight("1."), "\x1b[0;92m1.\x1b[0m")
+        self.assertEqual(cronrunner._color_title("Backup"), "\x1b[1mBackup\x1b[0m")
+
+    def test_high_contrast(self) -> None:
+        cronrunner._set_theme("high-contrast")
+        self.assertEqual(cronrunner._color_highlight("1."), "\x1b[1;93m1.\x1b[0m")
+        self.assertEqual(cronrunner._color_title("Backup"), "\x1b[1;4mBackup\x1b[0m")
+
+    def test_mono(self) -> None:
+        cronrunner._set_theme("mono")
+        self.assertEqual(cronrunner._color_highlight("1."), "1.")
+        self.assertEqual(cronrunner._color_title("Backup"), "Backup")
+        self.assertEqual(cronrunner._format_job_number(7, 3), "  7.")
+
+    def test_resolve_theme(self) -> None:
+        self.assertEqual(cronrunner._resolve_theme(None, {}), "default")
+        self.assertEqual(
+            cronrunner._resolve_theme(None, {"CRONRUNNER_THEME": "mono"}), "mono"
+        )
+        self.assertEqual(
+            cronrunner._resolve_theme("default", {"CRONRUNNER_THEME": "mono"}),
+            "default",
+        )
+        self.assertEqual(
+            cronrunner._resolve_theme(None, {"CRONRUNNER_THEME": "neon"}), "default"
+        )
+
+    def test_theme_flag(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            cronrunner.main(["--theme", "mono", "--color-test"])
+        self.assertNotIn("\x1b", stdout.getvalue())
+
+
+class TestFormatJobNumber(unittest.TestCase):
+    def test_natural_width(self) -> None:
+        self.assertEqual(cronrunner._format_job_number(7), "\x1b[0;92m7\x1b[0m.")
+
+    def test_forced_wider(self) -> None:
+        self.assertEqual(
+            cronrunner._format_job_number(7, 3), "  \x1b[0;92m7\x1b[0m."
+        )
+
+    def test_too_small_width_does_not_truncate(self) -> None:
+        self.assertEqual(
+            cronrunner._format_job_number(123, 1), "\x1b[0;92m123\x1b[0m."
+        )
+
+
+class TestJobIcons(unittest.TestCase):
+    def test_plain_job(self) -> None:
+        job = CronJob("* * * * *", ":", "")
+        self.assertEqual(cronrunner._make_job_icons(job, True), "   ")
+        self.assertEqual(cronrunner._make_job_icons(job, False), "  ")
+
+    def test_job_with_description(self) -> None:
+        job = CronJob("* * * * *", ":", "Do nothing.")
+        self.assertEqual(cronrunner._make_job_icons(job, True), "📝 ")
+        self.assertEqual(cronrunner._make_job_icons(job, False), "D ")
+
+    def test_reboot_job(self) -> None:
+        job = CronJob("@reboot", ":", "")
+        self.assertEqual(cronrunner._make_job_icons(job, True), "  ⟳")
+        self.assertEqual(cronrunner._make_job_icons(job, False), " R")
+
+    def test_reboot_job_with_description(self) -> None:
+        job = CronJob("@reboot", ":", "Do nothing.")
+        self.assertEqual(cronrunner._make_job_icons(job, True), "📝⟳")
+        self.assertEqual(cronrunner._make_job_icons(job, False), "DR")
+
+    def test_unicode_support(self) -> None:
+        self.assertTrue(cronrunner._supports_unicode_icons({}, "utf-8"))
+        self.assertTrue(cronrunner._supports_unicode_icons({}, "UTF-8"))
+        self.assertFalse(cronrunner._supports_unicode_icons({}, "ascii"))
+        self.assertFalse(cronrunner._supports_unicode_icons({}, None))
+        self.assertFalse(
+            cronrunner._supports_unicode_icons({"NO_COLOR": "1"}, "utf-8")
+        )
+
+
+class TestRebootNote(unittest.TestCase):
+    def test_reboot_job(self) -> None:
+        self.assertIn(
+            "This is an @reboot job.",
+            cronrunner._make_reboot_note(CronJob("@reboot", ":", "")),
+        )
+
+    def test_other_schedules(self) -> None:
+        self.assertEqual(cronrunner._make_reboot_note(CronJob("@daily", ":", "")), "")
+        self.assertEqual(
+            cronrunner._make_reboot_note(CronJob("0 0 * * *", ":", "")), ""
+        )
+
+
+class TestMain(unittest.TestCase):
+    CRONTAB: str = (
+        "# CronRunner Demo\n"
+        "\n"
+        "@reboot /usr/bin/bash ~/startup.sh\n"
+        "\n"
+        "FOO=bar\n"
+        "## Print variable.\n"
+        "  * * * * * echo $FOO\n"
+    )
+
+    def setUp(self) -> None:
+        nodes: list = CrontabParser().parse(self.CRONTAB)
+        self.crontab = Crontab(nodes, self.CRONTAB)
+        patcher = patch.object(cronrunner, "get_crontab", return_value=self.crontab)
+        patcher.start()
+        self.addCleanup(patcher.stop)
+        self.crontab.run = Mock(return_value=RunResult(0))
+
+    def test_locate_prints_line_number_and_raw_source_line(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            exit_code: int = cronrunner.main(["--locate", "2"])
+        self.assertEqual(exit_code, 0)
+        self.assertEqual(stdout.getvalue(), "7:   * * * * * echo $FOO\n")
+
+    def test_locate_job_without_source_line(self) -> None:
+        self.crontab.source = ""
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            exit_code: int = cronrunner.main(["--locate", "2"])
+        self.assertEqual(exit_code, 1)
+        self.assertIn("Cannot locate job in crontab source.", stdout.getvalue())
+
+    def test_crontab_killed_by_signal_is_reported(self) -> None:
+        cronrunner.get_crontab.side_effect = CrontabReadError(
+            "Cannot read crontab of current user.", signal=11
+        )
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            exit_code: int = cronrunner.main([])
+        self.assertEqual(exit_code, 1)
+        self.assertIn("terminated by signal 11", stdout.getvalue())
+
+    def test_prompt_is_written_to_given_writer(self) -> None:
+        writer = io.StringIO()
+        with patch("builtins.input", return_value="2"):
+            selection: str = cronrunner._get_user_selection(writer)
+        self.assertEqual(writer.getvalue(), ">>> Select a job to run: ")
+        self.assertEqual(selection, "2")
+
+    def test_plain_mode_keeps_prompt_out_of_stdout(self) -> None:
+        stdout = io.StringIO()
+        stderr = io.StringIO()
+        with redirect_stdout(stdout), redirect_stderr(stderr), patch(
+            "builtins.input", return_value=""
+        ):
+            exit_code: int = cronrunner.main(["--plain"])
+        self.assertEqual(exit_code, 0)
+        self.assertNotIn(">>> Select a job to run: ", stdout.getvalue())
+        self.assertEqual(stderr.getvalue(), ">>> Select a job to run: ")
+
+    def test_trim_command_only_affects_listing(self) -> None:
+        self.crontab.run = Mock()
+        job: CronJob = self.crontab.jobs[1]
+        job.job = "echo   $FOO"
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input", return_value="2"):
+            cronrunner.main(["--trim-command"])
+        self.assertIn("echo $FOO\x1b[0m\n", stdout.getvalue())
+        self.crontab.run.assert_called_once()
+        self.assertEqual(self.crontab.run.call_args.args[0].job, "echo   $FOO")
+
+    def test_shell_notice_is_printed_before_run(self) -> None:
+        self.crontab.run = Mock()
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch.dict(
+            cronrunner.os.environ, {"SHELL": "/bin/zsh"}
+        ), patch("builtins.input", return_value="1"):
+            cronrunner.main([])
+        self.assertIn("not in your shell (/bin/zsh)", stdout.getvalue())
+
+    def test_quiet_suppresses_shell_notice(self) -> None:
+        self.crontab.run = Mock()
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch.dict(
+            cronrunner.os.environ, {"SHELL": "/bin/zsh"}
+        ), patch("builtins.input", return_value="1"):
+            cronrunner.main(["--quiet"])
+        self.assertNotIn("not in your shell", stdout.getvalue())
+
+    def test_export_env(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            exit_code: int = cronrunner.main(["--export-env", "2"])
+        self.assertEqual(exit_code, 0)
+        self.assertEqual(stdout.getvalue(), 'export FOO="bar"\n')
+
+    def test_export_env_without_variables(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            exit_code: int = cronrunner.main(["--export-env", "1"])
+        self.assertEqual(exit_code, 0)
+        self.assertEqual(stdout.getvalue(), "")
+
+    def test_custom_prompt(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input", return_value=""):
+            cronrunner.main(["--prompt", "Which one? "])
+        self.assertTrue(stdout.getvalue().endswith("Which one? "))
+
+    def test_source_path_is_made_absolute(self) -> None:
+        self.crontab.run = Mock()
+        with redirect_stdout(io.StringIO()), patch("builtins.input", return_value="1"):
+            cronrunner.main(["--source", "~/.profile"])
+        self.assertEqual(
+            self.crontab.run.call_args.args[2], str(Path().home() / ".profile")
+        )
+
+    def test_cwd_defaults_to_home(self) -> None:
+        self.crontab.run = Mock()
+        with redirect_stdout(io.StringIO()), patch("builtins.input", return_value="1"):
+            cronrunner.main([])
+        self.assertEqual(self.crontab.run.call_args.args[3], Path().home())
+
+    def test_cwd_current(self) -> None:
+        self.crontab.run = Mock()
+        with redirect_stdout(io.StringIO()), patch("builtins.input", return_value="1"):
+            cronrunner.main(["--cwd-current"])
+        self.assertEqual(self.crontab.run.call_args.args[3], Path.cwd())
+
+    def test_check_without_problems(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            exit_code: int = cronrunner.main(["--check"])
+        self.assertEqual(exit_code, 0)
+        self.assertEqual(stdout.getvalue(), "No problems found.\n")
+
+    def test_check_reports_unknown_lines_with_hint(self) -> None:
+        self.crontab.nodes += CrontabParser().parse("#!/bin/bash\nunknown :")
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            exit_code: int = cronrunner.main(["--check"])
+        self.assertEqual(exit_code, 1)
+        self.assertIn(
+            "Unrecognized line: #!/bin/bash (shebang lines are not valid in crontabs)",
+            stdout.getvalue(),
+        )
+        self.assertIn("Unrecognized line: unknown :", stdout.getvalue())
+
+    def test_run_if_condition_met(self) -> None:
+        self.crontab.run = Mock()
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch(
+            "builtins.input", return_value="1"
+        ), patch.object(cronrunner.subprocess, "run", SUBPROCESS_RUN):
+            exit_code: int = cronrunner.main(["--run-if", "test 1 -eq 1"])
+        self.assertEqual(exit_code, 0)
+        self.crontab.run.assert_called_once()
+
+    def test_run_if_condition_not_met(self) -> None:
+        self.crontab.run = Mock()
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch(
+            "builtins.input", return_value="1"
+        ), patch.object(cronrunner.subprocess, "run", SUBPROCESS_RUN):
+            exit_code: int = cronrunner.main(["--run-if", "test 1 -eq 2"])
+        self.assertEqual(exit_code, 0)
+        self.crontab.run.assert_not_called()
+        self.assertIn("Condition not met, skipping job", stdout.getvalue())
+
+    def test_no_reboot_hides_reboot_jobs_but_keeps_numbering(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input", return_value=""):
+            cronrunner.main(["--no-reboot"])
+        self.assertNotIn("@reboot", stdout.getvalue())
+        self.assertIn("\x1b[0;92m2\x1b[0m. Print variable.", stdout.getvalue())
+
+    def test_no_reboot_rejects_numbers_of_hidden_jobs(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input", return_value="1"):
+            exit_code: int = cronrunner.main(["--no-reboot"])
+        self.assertEqual(exit_code, 1)
+        self.assertIn("Job 1 is hidden by --no-reboot.", stdout.getvalue())
+        self.crontab.run.assert_not_called()
+
+    def test_no_reboot_with_only_reboot_jobs(self) -> None:
+        self.crontab.nodes = self.crontab.jobs[:1]
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input") as input_:
+            exit_code: int = cronrunner.main(["--no-reboot"])
+        self.assertEqual(exit_code, 0)
+        self.assertEqual(stdout.getvalue(), "No jobs to run.\n")
+        input_.assert_not_called()
+
+    def test_color_test_does_not_need_crontab(self) -> None:
+        cronrunner.get_crontab.side_effect = CrontabReadError("Cannot read crontab.")
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            exit_code: int = cronrunner.main(["--color-test"])
+        self.assertEqual(exit_code, 0)
+        self.assertEqual(stdout.getvalue(), cronrunner._format_color_test() + "\n")
+
+    def test_id_width(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input", return_value=""):
+            cronrunner.main(["--id-width", "3"])
+        self.assertTrue(stdout.getvalue().startswith("  \x1b[0;92m1\x1b[0m. "))
+
+    def test_check_reports_missing_commands(self) -> None:
+        self.crontab.nodes.append(CronJob("@daily", "not-a-command", ""))
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            exit_code: int = cronrunner.main(["--check"])
+        self.assertEqual(exit_code, 1)
+        self.assertIn(
+            "Command not found: not-a-command (in 'not-a-command')", stdout.getvalue()
+        )
+
+    def test_missing_command_warning_before_run(self) -> None:
+        self.crontab.run = Mock()
+        self.crontab.nodes.append(CronJob("@daily", "not-a-command", ""))
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input", return_value="3"):
+            cronrunner.main([])
+        self.assertIn("Warning: command not found: not-a-command.", stdout.getvalue())
+        self.crontab.run.assert_called_once()
+
+    def test_menu_icons(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch(
+            "builtins.input", return_value=""
+        ), patch.dict(cronrunner.os.environ, {"NO_COLOR": "1"}):
+            cronrunner.main(["--menu-icons"])
+        self.assertIn("\x1b[0;92m1\x1b[0m.  R ", stdout.getvalue())
+        self.assertIn("\x1b[0;92m2\x1b[0m. D  Print variable.", stdout.getvalue())
+
+    def test_reboot_note_before_run(self) -> None:
+        self.crontab.run = Mock()
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input", return_value="1"):
+            cronrunner.main([])
+        self.assertIn("This is an @reboot job.", stdout.getvalue())
+
+    def test_hash(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            exit_code: int = cronrunner.main(["--hash", "2"])
+        self.assertEqual(exit_code, 0)
+        self.assertEqual(stdout.getvalue(), f"{cronrunner.djb2(b'echo $FOO'):x}\n")
+
+    def test_hash_with_algo_and_schedule(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            cronrunner.main(["--hash", "2", "--hash-algo", "fnv", "--hash-schedule"])
+        self.assertEqual(
+            stdout.getvalue(), f"{cronrunner.fnv1a(b'* * * * * echo $FOO'):x}\n"
+        )
+
+    def test_broken_pipe_is_a_clean_exit(self) -> None:
+        class ClosedWriter(io.StringIO):
+            def write(self, string: str) -> int:
+                raise BrokenPipeError
+
+        with redirect_stdout(ClosedWriter()):
+            exit_code: int = cronrunner.main([])
+        self.assertEqual(exit_code, 0)
+
+    def test_show_variables(self) -> None:
+        self.crontab.nodes = CrontabParser().parse(
+            "MAILTO=me@example.com\nFOO=bar\nPATH=/usr/bin:/bin\nCRON_TZ=UTC\n"
+        )
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            exit_code: int = cronrunner.main(["--show-variables"])
+        self.assertEqual(exit_code, 0)
+        self.assertEqual(
+            stdout.getvalue(),
+            "MAILTO=me@example.com \x1b[0;90m(special)\x1b[0m\n"
+            "FOO=bar\n"
+            "PATH=/usr/bin:/bin \x1b[0;90m(special)\x1b[0m\n"
+            "CRON_TZ=UTC \x1b[0;90m(special)\x1b[0m\n",
+        )
+
+    def test_show_variables_without_variables(self) -> None:
+        self.crontab.nodes = []
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            exit_code: int = cronrunner.main(["--show-variables"])
+        self.assertEqual(exit_code, 0)
+        self.assertEqual(stdout.getvalue(), "No variables.\n")
+
+    def test_interactive_runs_jobs_until_empty_selection(self) -> None:
+        self.crontab.run = Mock()
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch(
+            "builtins.input", side_effect=["1", "2", ""]
+        ) as input_:
+            exit_code: int = cronrunner.main(["--interactive"])
+        self.assertEqual(exit_code, 0)
+        self.assertEqual(input_.call_count, 3)
+        self.assertListEqual(
+            [call.args[0] for call in self.crontab.run.call_args_list],
+            self.crontab.jobs[:2],
+        )
+        self.assertEqual(stdout.getvalue().count(">>> Select a job to run: "), 3)
+
+    def test_interactive_continues_after_invalid_selection(self) -> None:
+        self.crontab.run = Mock()
+        with redirect_stdout(io.StringIO()), patch(
+            "builtins.input", side_effect=["999", "1", ""]
+        ):
+            exit_code: int = cronrunner.main(["--interactive"])
+        self.assertEqual(exit_code, 0)
+        self.crontab.run.assert_called_once()
+
+    def test_interactive_with_filter_without_match(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input") as input_:
+            exit_code: int = cronrunner.main(["--interactive", "--filter", "nothing"])
+        self.assertEqual(exit_code, 1)
+        self.assertEqual(stdout.getvalue().count("No jobs match the filter."), 1)
+        input_.assert_not_called()
+
+    def test_interactive_with_invalid_filter(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input") as input_:
+            exit_code: int = cronrunner.main(
+                ["--interactive", "--filter", "[", "--regex"]
+            )
+        self.assertEqual(exit_code, 1)
+        self.assertEqual(stdout.getvalue().count("Invalid filter:"), 1)
+        input_.assert_not_called()
+
+    def test_not_interactive_runs_one_job(self) -> None:
+        self.crontab.run = Mock()
+        with redirect_stdout(io.StringIO()), patch(
+            "builtins.input", side_effect=["1", "2", ""]
+        ) as input_:
+            cronrunner.main([])
+        self.assertEqual(input_.call_count, 1)
+        self.crontab.run.assert_called_once()
+
+    def test_env_from_job(self) -> None:
+        self.crontab.run = Mock()
+        with redirect_stdout(io.StringIO()), patch("builtins.input", return_value="1"):
+            exit_code: int = cronrunner.main(["--env-from-job", "2"])
+        self.assertEqual(exit_code, 0)
+        self.assertIs(
+            self.crontab.run.call_args.kwargs["env_from"], self.crontab.jobs[1]
+        )
+
+    def test_env_from_invalid_job(self) -> None:
+        self.crontab.run = Mock()
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input") as input_:
+            exit_code: int = cronrunner.main(["--env-from-job", "999"])
+        self.assertEqual(exit_code, 1)
+        self.assertIn("Invalid job number for --env-from-job.", stdout.getvalue())
+        input_.assert_not_called()
+
+    def test_banner(self) -> None:
+        self.crontab.run = Mock()
+        job_number: int = next(
+            i for i, job in enumerate(self.crontab.jobs, start=1) if job.description
+        )
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch(
+            "builtins.input", return_value=str(job_number)
+        ):
+            cronrunner.main(["--banner"])
+        description: str = self.crontab.jobs[job_number - 1].description
+        self.assertIn(f"\x1b[1m== {description} ==\x1b[0m\n", stdout.getvalue())
+
+    @unittest.skipIf(cronrunner.fcntl is None, "Unix only")
+    def test_single_instance_refuses_running_job(self) -> None:
+        self.crontab.run = Mock()
+        with tempfile.TemporaryDirectory() as directory, patch.dict(
+            cronrunner.os.environ, {"XDG_RUNTIME_DIR": directory}
+        ):
+            path: Path = cronrunner._make_lock_path(self.crontab.jobs[0], os.environ)
+            lock: TextIO = cronrunner._acquire_lock(path)
+            stdout = io.StringIO()
+            with redirect_stdout(stdout), patch("builtins.input", return_value="1"):
+                exit_code: int = cronrunner.main(["--single-instance"])
+            lock.close()
+        self.assertEqual(exit_code, 1)
+        self.assertIn("This job is already running.", stdout.getvalue())
+        self.crontab.run.assert_not_called()
+
+    @unittest.skipIf(cronrunner.fcntl is None, "Unix only")
+    def test_single_instance_runs_job(self) -> None:
+        self.crontab.run = Mock()
+        with tempfile.TemporaryDirectory() as directory, patch.dict(
+            cronrunner.os.environ, {"XDG_RUNTIME_DIR": directory}
+        ):
+            with redirect_stdout(io.StringIO()), patch(
+                "builtins.input", return_value="1"
+            ):
+                exit_code: int = cronrunner.main(["--single-instance"])
+            path: Path = cronrunner._make_lock_path(self.crontab.jobs[0], os.environ)
+            cronrunner._acquire_lock(path).close()  # Released.
+        self.assertEqual(exit_code, 0)
+        self.crontab.run.assert_called_once()
+
+    def test_as_user(self) -> None:
+        self.crontab.run = Mock()
+        with redirect_stdout(io.StringIO()), patch(
+            "builtins.input", return_value="1"
+        ), patch.object(cronrunner.shutil, "which", return_value="/usr/bin/sudo"):
+            exit_code: int = cronrunner.main(["--as-user", "backup"])
+        self.assertEqual(exit_code, 0)
+        self.assertListEqual(
+            self.crontab.run.call_args.args[1], ["sudo", "-u", "backup", "--"]
+        )
+
+    def test_without_as_user(self) -> None:
+        self.crontab.run = Mock()
+        with redirect_stdout(io.StringIO()), patch("builtins.input", return_value="1"):
+            cronrunner.main([])
+        self.assertListEqual(self.crontab.run.call_args.args[1], [])
+
+    def test_as_user_without_sudo(self) -> None:
+        self.crontab.run = Mock()
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch(
+            "builtins.input", return_value="1"
+        ), patch.object(cronrunner.shutil, "which", return_value=None):
+            exit_code: int = cronrunner.main(["--as-user", "backup"])
+        self.assertEqual(exit_code, 1)
+        self.assertIn("Cannot find 'sudo'.", stdout.getvalue())
+        self.crontab.run.assert_not_called()
+
+    def test_as_user_with_as_script(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch(
+            "builtins.input", return_value="1"
+        ), patch.object(cronrunner.shutil, "which", return_value="/usr/bin/sudo"):
+            exit_code: int = cronrunner.main(["--as-user", "backup", "--as-script"])
+        self.assertEqual(exit_code, 1)
+        self.assertIn(
+            "--as-user cannot be combined with --as-script.", stdout.getvalue()
+        )
+        self.crontab.run.assert_not_called()
+
+    def test_wrap(self) -> None:
+        self.crontab.run = Mock()
+        with redirect_stdout(io.StringIO()), patch(
+            "builtins.input", return_value="1"
+        ), patch.object(cronrunner.shutil, "which", return_value="/usr/bin/nice"):
+            cronrunner.main(["--nice", "10", "--wrap", "flock -n /tmp/cron.lock"])
+        self.assertListEqual(
+            self.crontab.run.call_args.args[1],
+            ["nice", "-n", "10", "flock", "-n", "/tmp/cron.lock"],
+        )
+
+    def test_wrap_invalid(self) -> None:
+        self.crontab.run = Mock()
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input", return_value="1"):
+            exit_code: int = cronrunner.main(["--wrap", "flock '"])
+        self.assertEqual(exit_code, 1)
+        self.assertIn("Invalid wrapper", stdout.getvalue())
+        self.crontab.run.assert_not_called()
+
+    def test_tsv(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            exit_code: int = cronrunner.main(["--tsv"])
+        self.assertEqual(exit_code, 0)
+        lines: list = stdout.getvalue().splitlines()
+        self.assertEqual(lines[0], "id\tfingerprint\tschedule\tcommand")
+        self.assertEqual(len(lines), len(self.crontab.jobs) + 1)
+        self.assertNotIn("\x1b", stdout.getvalue())
+
+    def test_as_yaml(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            exit_code: int = cronrunner.main(["--as-yaml"])
+        self.assertEqual(exit_code, 0)
+        self.assertEqual(stdout.getvalue().count("- id: "), len(self.crontab.jobs))
+        self.assertNotIn("\x1b", stdout.getvalue())
+
+    def test_tsv_with_match_schedule(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            cronrunner.main(["--tsv", "--match-schedule", "* *"])
+        lines: list = stdout.getvalue().splitlines()
+        self.assertEqual(len(lines), 2)
+        self.assertTrue(lines[1].startswith("2\t"))
+
+    def test_as_yaml_with_filter(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            cronrunner.main(["--as-yaml", "--filter", "FOO"])
+        self.assertEqual(stdout.getvalue().count("- id: "), 1)
+        self.assertIn("- id: 2\n", stdout.getvalue())
+
+    def test_as_yaml_with_no_reboot_and_no_match(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            cronrunner.main(["--as-yaml", "--match-schedule", "@reboot", "--no-reboot"])
+        self.assertEqual(stdout.getvalue(), "[]\n")
+
+    def test_snapshot_then_only_changed(self) -> None:
+        with tempfile.TemporaryDirectory() as directory:
+            path: str = str(Path(directory) / "snapshot")
+            with redirect_stdout(io.StringIO()):
+                self.assertEqual(cronrunner.main(["--snapshot", path]), 0)
+
+            stdout = io.StringIO()
+            with redirect_stdout(stdout):
+                self.assertEqual(cronrunner.main(["--only-changed", path]), 0)
+            self.assertEqual(stdout.getvalue(), "No changes.\n")
+
+            self.crontab.nodes.append(CronJob("@weekly", "cleanup.sh", ""))
+            stdout = io.StringIO()
+            with redirect_stdout(stdout):
+                self.assertEqual(cronrunner.main(["--only-changed", path]), 0)
+            self.assertIn("@weekly cleanup.sh", stdout.getvalue())
+            self.assertNotIn("No changes.", stdout.getvalue())
+
+    def test_only_changed_warns_about_duplicate_fingerprints(self) -> None:
+        self.crontab.nodes.append(self.crontab.jobs[1])
+        with tempfile.TemporaryDirectory() as directory:
+            path: str = str(Path(directory) / "snapshot")
+            stderr = io.StringIO()
+            with redirect_stdout(io.StringIO()), redirect_stderr(stderr):
+                cronrunner.main(["--snapshot", path])
+                cronrunner.main(["--only-changed", path])
+        fingerprint: str = self.crontab.jobs[1].fingerprint_hex(include_schedule=True)
+        self.assertEqual(stderr.getvalue().count("share a fingerprint"), 2)
+        self.assertIn(fingerprint, stderr.getvalue())
+
+    def test_only_changed_missing_snapshot(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            exit_code: int = cronrunner.main(["--only-changed", "/nonexistent/file"])
+        self.assertEqual(exit_code, 1)
+        self.assertIn("Cannot read snapshot", stdout.getvalue())
+
+    def test_syntax_check(self) -> None:
+        self.crontab.check_syntax = Mock(return_value=RunResult(0))
+        self.crontab.run = Mock()
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input", return_value="1"):
+            exit_code: int = cronrunner.main(["--syntax-check"])
+        self.assertEqual(exit_code, 0)
+        self.assertIn("Syntax OK.", stdout.getvalue())
+        self.crontab.run.assert_not_called()
+
+    def test_syntax_check_error(self) -> None:
+        self.crontab.check_syntax = Mock(return_value=RunResult(2))
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input", return_value="1"):
+            exit_code: int = cronrunner.main(["--syntax-check"])
+        self.assertEqual(exit_code, 1)
+        self.assertIn("Syntax error.", stdout.getvalue())
+
+    def test_syntax_check_unsupported_shell(self) -> None:
+        self.crontab.check_syntax = Mock(return_value=None)
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input", return_value="1"):
+            exit_code: int = cronrunner.main(["--syntax-check"])
+        self.assertEqual(exit_code, 0)
+        self.assertIn("Cannot check syntax", stdout.getvalue())
+
+    def test_on_exit(self) -> None:
+        self.crontab.run = Mock(return_value=RunResult(2))
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input", return_value="1"):
+            cronrunner.main(["--on-exit", "1=Failed", "--on-exit", "2=Config error"])
+        self.assertTrue(stdout.getvalue().endswith("Config error\n"))
+        self.assertNotIn("Failed", stdout.getvalue())
+
+    def test_audit_log(self) -> None:
+        self.crontab.run.return_value = RunResult(3)
+        with tempfile.TemporaryDirectory() as tmp:
+            path = Path(tmp) / "audit.log"
+            with redirect_stdout(io.StringIO()), patch(
+                "builtins.input", return_value="2"
+            ):
+                cronrunner.main(["--audit-log", str(path), "--hash-algo", "fnv"])
+            columns: list = path.read_text().rstrip("\n").split("\t")
+        job: CronJob = self.crontab.jobs[1]
+        self.assertEqual(columns[2], "2")
+        self.assertEqual(columns[3], job.fingerprint_hex("fnv", include_schedule=True))
+        self.assertEqual(columns[4], "3")
+        self.assertEqual(columns[6], job.job)
+
+    def test_timeout(self) -> None:
+        self.crontab.run.return_value = RunResult(-15, timed_out=True)
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input", return_value="1"):
+            cronrunner.main(["--timeout", "30"])
+        self.assertEqual(self.crontab.run.call_args.kwargs["timeout"], 30)
+        self.assertIn("Job timed out after 30 seconds.", stdout.getvalue())
+
+    def test_env_diff(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch(
+            "builtins.input", return_value="2"
+        ), patch.dict(cronrunner.os.environ, {"FOO": "baz"}):
+            cronrunner.main(["--env-diff"])
+        self.assertIn("-FOO=baz\n+FOO=bar\n", stdout.getvalue())
+
+    def test_env_diff_ignores_variables_that_are_not_exported(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch(
+            "builtins.input", return_value="2"
+        ), patch.dict(cronrunner.os.environ):
+            cronrunner.os.environ.pop("FOO", None)
+            cronrunner.main(["--env-diff"])
+        self.assertIn("No environment differences.", stdout.getvalue())
+
+    def test_user(self) -> None:
+        with redirect_stdout(io.StringIO()), patch("builtins.input", return_value=""):
+            cronrunner.main(["--user", "alice"])
+        self.assertEqual(cronrunner.get_crontab.call_args.args[1], "alice")
+
+    def test_scope(self) -> None:
+        with patch.object(cronrunner.shutil, "which", return_value="/usr/bin/x"):
+            with redirect_stdout(io.StringIO()), patch(
+                "builtins.input", return_value="2"
+            ):
+                cronrunner.main(["--scope", "--quiet"])
+        self.assertListEqual(
+            self.crontab.run.call_args.args[1][:3],
+            ["systemd-run", "--user", "--scope"],
+        )
+
+    def test_scope_without_systemd_run(self) -> None:
+        stdout = io.StringIO()
+        with patch.object(cronrunner.shutil, "which", return_value=None):
+            with redirect_stdout(stdout), patch("builtins.input", return_value="2"):
+                cronrunner.main(["--scope", "--quiet"])
+        self.assertIn("Cannot find 'systemd-run', ignoring --scope.", stdout.getvalue())
+        self.assertListEqual(self.crontab.run.call_args.args[1], [])
+
+    def test_export_termsize(self) -> None:
+        with patch.object(
+            cronrunner,
+            "_get_terminal_size",
+            return_value=os.terminal_size((120, 40)),
+        ), redirect_stdout(io.StringIO()), patch("builtins.input", return_value="2"):
+            cronrunner.main(["--export-termsize"])
+        self.assertDictEqual(
+            self.crontab.run.call_args.kwargs["env"], {"COLUMNS": "120", "LINES": "40"}
+        )
+
+    def test_export_termsize_without_terminal(self) -> None:
+        stdout = io.StringIO()
+        with patch.object(
+            cronrunner, "_get_terminal_size", return_value=None
+        ), redirect_stdout(stdout), patch("builtins.input", return_value="2"):
+            cronrunner.main(["--export-termsize"])
+        self.assertIn("Cannot detect terminal size", stdout.getvalue())
+        self.assertIsNone(self.crontab.run.call_args.kwargs["env"])
+
+    def test_no_trim(self) -> None:
+        with redirect_stdout(io.StringIO()), patch("builtins.input", return_value=""):
+            cronrunner.main(["--no-trim"])
+        self.assertFalse(cronrunner.get_crontab.call_args.args[0].trim_commands)
+
+    def test_filter(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input", return_value="2"):
+            cronrunner.main(["--filter", "FOO"])
+        self.assertNotIn("startup.sh", stdout.getvalue())
+        self.assertIn("2\x1b[0m. Print variable.", stdout.getvalue())
+        self.assertEqual(self.crontab.run.call_args.args[0], self.crontab.jobs[1])
+
+    def test_match_schedule(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input", return_value=""):
+            cronrunner.main(["--match-schedule", "@reboot"])
+        self.assertIn("startup.sh", stdout.getvalue())
+        self.assertNotIn("Print variable.", stdout.getvalue())
+
+    def test_filter_without_match(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            exit_code: int = cronrunner.main(["--filter", "nothing"])
+        self.assertEqual(exit_code, 1)
+        self.assertIn("No jobs match the filter.", stdout.getvalue())
+
+    def test_filter_rejects_numbers_of_hidden_jobs(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input", return_value="1"):
+            exit_code: int = cronrunner.main(["--filter", "FOO"])
+        self.assertEqual(exit_code, 1)
+        self.assertIn("Job 1 is hidden by the filter.", stdout.getvalue())
+        self.crontab.run.assert_not_called()
+
+    def test_filter_matching_only_hidden_jobs(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input") as input_:
+            exit_code: int = cronrunner.main(
+                ["--match-schedule", "@reboot", "--no-reboot"]
+            )
+        self.assertEqual(exit_code, 1)
+        self.assertIn("No jobs match the filter.", stdout.getvalue())
+        input_.assert_not_called()
+
+    def test_filter_invalid_regex(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            exit_code: int = cronrunner.main(["--filter", "(", "--regex"])
+        self.assertEqual(exit_code, 1)
+        self.assertIn("Invalid filter:", stdout.getvalue())
+
+    def test_file(self) -> None:
+        with patch.object(
+            cronrunner, "get_crontab_from_file", return_value=self.crontab
+        ) as get_crontab_from_file:
+            with redirect_stdout(io.StringIO()), patch(
+                "builtins.input", return_value=""
+            ):
+                cronrunner.main(["--file", "my.crontab"])
+        self.assertEqual(get_crontab_from_file.call_args.args[0], Path("my.crontab"))
+        cronrunner.get_crontab.assert_not_called()
+
+    def test_file_and_user_are_mutually_exclusive(self) -> None:
+        with redirect_stderr(io.StringIO()), self.assertRaises(SystemExit):
+            cronrunner.main(["--file", "my.crontab", "--user", "alice"])
+
+    def test_stats(self) -> None:
+        self.crontab.run = Mock(return_value=RunResult(0, ResourceUsage(0.25, 0.5)))
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input", return_value="1"):
+            cronrunner.main(["--stats"])
+        self.assertIn("user 0.250s, system 0.500s", stdout.getvalue())
+
+    def test_no_stats_by_default(self) -> None:
+        self.crontab.run = Mock(return_value=RunResult(0, ResourceUsage(0.25, 0.5)))
+        stdout = io.StringIO()
+        with redirect_stdout(stdout), patch("builtins.input", return_value="1"):
+            cronrunner.main([])
+        self.assertNotIn("user 0.250s", stdout.getvalue())
+
+    def test_locate_invalid_job_number(self) -> None:
+        stdout = io.StringIO()
+        with redirect_stdout(stdout):
+            exit_code: int = cronrunner.main(["--locate", "3"])
+        self.assertEqual(exit_code, 1)
+        self.assertIn("Invalid job number.", stdout.getvalue())
 
 
 if __name__ == "__main__":